@@ -0,0 +1,254 @@
+//! Trait bounds for the implementations of generic template types.
+
+use syn::punctuated::Punctuated;
+use syn::{
+	parse_quote, Expr, Field, GenericArgument, GenericParam, Generics, Ident,
+	Member, PathArguments, Type, TypeParamBound, Variant, WherePredicate,
+};
+use t4rust_build::lexer::{self, Token};
+use t4rust_build::TemplatePart;
+
+/// Adds `predicates` to the where clause of `generics`.
+pub(crate) fn add(generics: &mut Generics, predicates: Vec<WherePredicate>) {
+	if !predicates.is_empty() {
+		generics.make_where_clause().predicates.extend(predicates);
+	}
+}
+
+/// The names whose types are known to the template code.
+#[derive(Clone, Default)]
+pub(crate) struct Scope<'a> {
+	/// The fields of `self`, tuple fields are named by their index.
+	fields: Vec<(String, &'a Type)>,
+	/// The local variables, e.g. the fields of an enum variant which its match
+	/// arm binds.
+	locals: Vec<(String, &'a Type)>,
+}
+
+impl<'a> Scope<'a> {
+	/// The scope of the template of a struct or union.
+	pub(crate) fn of_fields(
+		fields: impl IntoIterator<Item = &'a Field>,
+	) -> Self
+	{
+		let fields = fields
+			.into_iter()
+			.enumerate()
+			.map(|(i, field)| match &field.ident {
+				Some(ident) => (ident.to_string(), &field.ty),
+				None => (i.to_string(), &field.ty),
+			})
+			.collect();
+		Self { fields, locals: Vec::new() }
+	}
+
+	/// The scope of the template of an enum variant, its fields are bound to
+	/// their names or to `_0`, `_1`, ... for tuple variants.
+	pub(crate) fn of_variant(variant: &'a Variant) -> Self {
+		let locals = variant
+			.fields
+			.iter()
+			.enumerate()
+			.map(|(i, field)| match &field.ident {
+				Some(ident) => (ident.to_string(), &field.ty),
+				None => (format!("_{}", i), &field.ty),
+			})
+			.collect();
+		Self { fields: Vec::new(), locals }
+	}
+
+	/// The type of an expression, if it is a field or a local variable.
+	fn type_of(&self, expr: &Expr) -> Option<&'a Type> {
+		match expr {
+			Expr::Reference(expr) => self.type_of(&expr.expr),
+			Expr::Paren(expr) => self.type_of(&expr.expr),
+			Expr::Group(expr) => self.type_of(&expr.expr),
+			Expr::Path(path) if path.qself.is_none() => {
+				let ident = path.path.get_ident()?.to_string();
+				let local = self.locals.iter().rev().find(|(n, _)| *n == ident);
+				local.map(|(_, ty)| *ty)
+			}
+			Expr::Field(field) => {
+				match &*field.base {
+					Expr::Path(path) if path.path.is_ident("self") => {}
+					_ => return None,
+				}
+				let member = match &field.member {
+					Member::Named(ident) => ident.to_string(),
+					Member::Unnamed(index) => index.index.to_string(),
+				};
+				let field = self.fields.iter().find(|(n, _)| *n == member);
+				field.map(|(_, ty)| *ty)
+			}
+			// Iterating over a collection yields its items
+			Expr::MethodCall(call)
+				if call.method == "iter" && call.args.is_empty() =>
+			{
+				self.type_of(&call.receiver)
+			}
+			_ => None,
+		}
+	}
+}
+
+/// The inferred `T: Display` bounds for the type parameters whose values are
+/// printed by the `blocks` of a template.
+///
+/// A bound is only inferred if an expression block prints a value of type `T`
+/// or `&T`, e.g. a field of type `T` or the loop variable of
+/// `for item in &self.items` with `items: Vec<T>`. Other expressions, like
+/// `self.items.len()`, need no bound. Type parameters which are already bound
+/// by `Display` are left alone.
+pub(crate) fn display(
+	generics: &Generics,
+	scope: &Scope,
+	blocks: &[TemplatePart],
+) -> Vec<WherePredicate>
+{
+	let params: Vec<&Ident> = generics
+		.params
+		.iter()
+		.filter_map(|param| match param {
+			GenericParam::Type(param) if !has_display(&param.bounds) => {
+				Some(&param.ident)
+			}
+			_ => None,
+		})
+		.filter(|ident| !has_where_display(generics, ident))
+		.collect();
+
+	let mut scope = scope.clone();
+	let mut printed = Vec::new();
+	for block in blocks {
+		match block {
+			TemplatePart::Code(code) => {
+				for (name, iterable) in loops(code) {
+					let item = scope.type_of(&iterable).and_then(item_type);
+					if let Some(item) = item {
+						scope.locals.push((name, item));
+					}
+				}
+			}
+			TemplatePart::Expr(expr) => {
+				let ty = syn::parse_str(expr)
+					.ok()
+					.and_then(|expr| scope.type_of(&expr));
+				if let Some(ident) = ty.and_then(type_param) {
+					if params.contains(&ident) && !printed.contains(&ident) {
+						printed.push(ident);
+					}
+				}
+			}
+			_ => {}
+		}
+	}
+	printed
+		.into_iter()
+		.map(|ident| parse_quote! { #ident: ::std::fmt::Display })
+		.collect()
+}
+
+/// The `for name in iterable` loops in a code block whose pattern is a single
+/// name.
+fn loops(code: &str) -> Vec<(String, Expr)> {
+	let tokens = lexer::tokenize(code);
+	let mut res = Vec::new();
+	for (i, window) in tokens.windows(3).enumerate() {
+		let name = match window {
+			[
+				(_, Token::Ident("for")),
+				(_, Token::Ident(name)),
+				(_, Token::Ident("in")),
+			] => name,
+			_ => continue,
+		};
+		// The iterable ends at the brace of the loop body
+		let rest = &tokens[i + 3..];
+		let start = rest.first().map_or(code.len(), |(offset, _)| *offset);
+		let mut depth = 0;
+		let end = rest
+			.iter()
+			.find(|(_, token)| {
+				match token {
+					Token::Punct('(' | '[') => depth += 1,
+					Token::Punct(')' | ']') => depth -= 1,
+					Token::Punct('{') => return depth == 0,
+					_ => {}
+				}
+				false
+			})
+			.map_or(code.len(), |(offset, _)| *offset);
+		if let Ok(iterable) = syn::parse_str(&code[start..end]) {
+			res.push((name.to_string(), iterable));
+		}
+	}
+	res
+}
+
+/// The type of the items of a collection type, e.g. `T` for `Vec<T>`, `&[T]`
+/// or `Option<T>`.
+fn item_type(ty: &Type) -> Option<&Type> {
+	match ty {
+		Type::Reference(ty) => item_type(&ty.elem),
+		Type::Paren(ty) => item_type(&ty.elem),
+		Type::Group(ty) => item_type(&ty.elem),
+		Type::Slice(ty) => Some(&ty.elem),
+		Type::Array(ty) => Some(&ty.elem),
+		Type::Path(path) if path.qself.is_none() => {
+			let segment = path.path.segments.last()?;
+			match &segment.arguments {
+				PathArguments::AngleBracketed(args) if args.args.len() == 1 => {
+					match &args.args[0] {
+						GenericArgument::Type(ty) => Some(ty),
+						_ => None,
+					}
+				}
+				_ => None,
+			}
+		}
+		_ => None,
+	}
+}
+
+/// The type parameter `T` if `ty` is `T` or a reference to it.
+fn type_param(ty: &Type) -> Option<&Ident> {
+	match ty {
+		Type::Reference(ty) => type_param(&ty.elem),
+		Type::Paren(ty) => type_param(&ty.elem),
+		Type::Group(ty) => type_param(&ty.elem),
+		Type::Path(path) if path.qself.is_none() => path.path.get_ident(),
+		_ => None,
+	}
+}
+
+/// Checks if `bounds` contain `Display`, by any path.
+fn has_display<P>(bounds: &Punctuated<TypeParamBound, P>) -> bool {
+	bounds.iter().any(|bound| match bound {
+		TypeParamBound::Trait(bound) => {
+			let last = bound.path.segments.last();
+			last.is_some_and(|segment| segment.ident == "Display")
+		}
+		_ => false,
+	})
+}
+
+/// Checks if the where clause bounds the type parameter `ident` directly by
+/// `Display`.
+fn has_where_display(generics: &Generics, ident: &Ident) -> bool {
+	let where_clause = match &generics.where_clause {
+		Some(where_clause) => where_clause,
+		None => return false,
+	};
+	where_clause.predicates.iter().any(|predicate| match predicate {
+		WherePredicate::Type(predicate) => {
+			let param = match &predicate.bounded_ty {
+				Type::Path(path) => {
+					path.qself.is_none() && path.path.is_ident(ident)
+				}
+				_ => false,
+			};
+			param && has_display(&predicate.bounds)
+		}
+		_ => false,
+	})
+}
@@ -0,0 +1,168 @@
+//! Generates a module with a template struct for every template file in a
+//! directory.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use syn::Visibility;
+use t4rust_build::config::CrateConfig;
+use t4rust_build::parameters;
+
+const TEMPLATE_EXTENSION: &str = "tt";
+
+/// Generates the modules for the directory `dir`, which is relative to the
+/// template root of the crate.
+///
+/// Subdirectories become nested modules, hidden directories and those without
+/// templates are skipped.
+pub(crate) fn generate_modules(
+	config: &CrateConfig,
+	dir: &Path,
+) -> TokenStream
+{
+	let absolute = config.root.join(dir);
+	let entries = std::fs::read_dir(&absolute).unwrap_or_else(|err| {
+		panic!("Could not read directory {}: {}", absolute.display(), err)
+	});
+
+	// Sorted by module name, so the output does not depend on the file system
+	let mut modules: BTreeMap<String, PathBuf> = BTreeMap::new();
+	for entry in entries {
+		let path = entry.expect("Could not read directory entry").path();
+		let include = if path.is_dir() {
+			!is_hidden(&path) && has_templates(&path)
+		} else {
+			is_template(&path)
+		};
+		if !include {
+			continue;
+		}
+		let name = module_name(&path).unwrap_or_else(|| {
+			panic!("Can not derive a module name from {}", path.display())
+		});
+		if let Some(other) = modules.get(&name) {
+			panic!(
+				"The templates {} and {} both generate the module `{}`",
+				other.display(),
+				path.display(),
+				name
+			);
+		}
+		modules.insert(name, path);
+	}
+
+	let vis: Visibility = syn::parse_quote! { pub };
+	let modules = modules.iter().map(|(name, path)| {
+		let module = Ident::new(name, proc_macro2::Span::call_site());
+		let relative = dir.join(path.file_name().unwrap());
+		let content = if path.is_dir() {
+			generate_modules(config, &relative)
+		} else {
+			let name = parameters::type_name(path).unwrap_or_else(|| {
+				panic!("Can not derive a struct name from {}", path.display())
+			});
+			t4rust_build::template_struct(config, &vis, &name, &relative)
+				.unwrap_or_else(|err| panic!("{}", err))
+		};
+		quote! {
+			pub mod #module {
+				#[allow(unused_imports)]
+				use super::*;
+
+				#content
+			}
+		}
+	});
+
+	quote! { #(#modules)* }
+}
+
+fn is_template(path: &Path) -> bool {
+	path.is_file()
+		&& path.extension().and_then(|e| e.to_str()) == Some(TEMPLATE_EXTENSION)
+}
+
+/// Directories like `.git`.
+fn is_hidden(path: &Path) -> bool {
+	path.file_name()
+		.and_then(|name| name.to_str())
+		.is_some_and(|name| name.starts_with('.'))
+}
+
+/// Checks if `dir` or one of its subdirectories which are not hidden contain
+/// a template.
+fn has_templates(dir: &Path) -> bool {
+	let entries = match std::fs::read_dir(dir) {
+		Ok(entries) => entries,
+		Err(_) => return false,
+	};
+	entries.filter_map(Result::ok).map(|entry| entry.path()).any(|path| {
+		if path.is_dir() {
+			!is_hidden(&path) && has_templates(&path)
+		} else {
+			is_template(&path)
+		}
+	})
+}
+
+/// The module name for a template file or directory, `order_shipped` for
+/// `order-shipped.html.tt`.
+fn module_name(path: &Path) -> Option<String> {
+	let stem = path.file_name()?.to_str()?.split('.').next()?;
+	let name = stem.to_lowercase().replace(['-', ' '], "_");
+	syn::parse_str::<Ident>(&name).ok()?;
+	Some(name)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn module_names() {
+		let name = |path| module_name(Path::new(path));
+		assert_eq!(name("mail/welcome.html.tt").unwrap(), "welcome");
+		assert_eq!(name("order-shipped.tt").unwrap(), "order_shipped");
+		assert_eq!(name("Reset Password.txt.tt").unwrap(), "reset_password");
+		assert_eq!(name("mail/admin").unwrap(), "admin");
+		assert_eq!(name("type.tt"), None);
+	}
+
+	#[test]
+	fn skipped_directories() {
+		let dir = std::env::temp_dir()
+			.join(format!("t4rust-skipped-{}", std::process::id()));
+		for sub in &[".git", "assets", "mail/admin"] {
+			std::fs::create_dir_all(dir.join(sub)).unwrap();
+		}
+		std::fs::write(dir.join(".git/HEAD"), "").unwrap();
+		std::fs::write(dir.join("assets/logo.png"), "").unwrap();
+		std::fs::write(dir.join("mail/admin/report.tt"), "Report").unwrap();
+		let config =
+			CrateConfig { root: dir.clone(), ..CrateConfig::default() };
+		let code = generate_modules(&config, Path::new("")).to_string();
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert!(code.contains("pub mod mail"));
+		assert!(code.contains("pub mod admin"));
+		assert!(!code.contains("pub mod assets"));
+	}
+
+	#[test]
+	#[should_panic(expected = "both generate the module `welcome`")]
+	fn name_collision() {
+		let dir = std::env::temp_dir()
+			.join(format!("t4rust-collision-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(dir.join("welcome.html.tt"), "").unwrap();
+		std::fs::write(dir.join("welcome.txt.tt"), "").unwrap();
+		let config =
+			CrateConfig { root: dir.clone(), ..CrateConfig::default() };
+		let res = std::panic::catch_unwind(|| {
+			generate_modules(&config, Path::new(""))
+		});
+		std::fs::remove_dir_all(&dir).unwrap();
+		std::panic::resume_unwind(res.unwrap_err());
+	}
+}
@@ -10,10 +10,10 @@
 //! // Add this attribute to use a template
 //! #[derive(Template)]
 //! // Specify the path to the template file here
-//! #[TemplatePath = "./examples/doc_example1.tt"]
-//! // Add this attribute if you want to get debug parsing information
-//! // This also enables writing temporary files, you might get better error messages.
-//! //#[TemplateDebug]
+//! #[template(path = "./examples/doc_example1.tt")]
+//! // Add `debug` to write the parsed template and the generated code to
+//! // `target/t4rust`, you might also get better error messages.
+//! //#[template(path = "./examples/doc_example1.tt", debug)]
 //! struct Example {
 //!     // Add fields to the struct you want to use in the template
 //!     name: String,
@@ -32,7 +32,7 @@
 //! `doc_example1.tt`:
 //! ```text
 //! Hello From Template!
-//! My Name is: <# write!(_fmt, "{}", self.name)?; #>
+//! My Name is: <# out!("{}", self.name)?; #>
 //! I like to eat <#= self.food #>.
 //! <# for num in 0..self.num { #>Num:<#= num + 1 #>
 //! <# } #>
@@ -60,15 +60,372 @@
 //!
 //! You can use `<#= expr #>` to print out a single expression.
 //!
-//! Maybe you noticed the magical `_fmt` in the template. This variable gives you
-//! access to the formatter and e.g. enables you to write functions in your
-//! template. `<# write!(_fmt, "{}", self.name)?; #>` is equal to `<#= self.name #>`.
+//! Maybe you noticed the `out!` macro in the template. It works like `write!`
+//! on the formatter of the template and e.g. enables you to write functions in
+//! your template. `<# out!("{}", self.name)?; #>` is equal to `<#= self.name #>`.
 //!
-//! **Warning**: Make sure to never create a variable called `_fmt`! You will get
-//! weird compiler errors.
+//! The formatter itself is still reachable as `_fmt`, but this name is
+//! deprecated. Rebinding `_fmt` (e.g. `let _fmt = ...`) is rejected.
 //!
 //! # Features
 //!
+//! ## Template attribute
+//!
+//! All options of a template are set in the `#[template(...)]` attribute:
+//! ```rust,ignore
+//! #[derive(Template)]
+//! #[template(path = "./templates/page.tt", escape = "html", cleanws, debug)]
+//! struct Page { /* ... */ }
+//! ```
+//!
+//! - `path`: The template file, relative to the crate root.
+//! - `name`: Declares a [named template](#named-templates).
+//! - `escape`: The initial escape function, see [Auto-escaping](#auto-escaping).
+//! - `cleanws`: Enables the `cleanws` option of the `template` directive.
+//! - `strict`: Enables [strict escaping](#strict-escaping).
+//! - `restricted`, `allow`: Limit the code in the template, see
+//!   [Restricted templates](#restricted-templates).
+//! - `debug`: Writes [debug artifacts](#debug-artifacts) for the template.
+//! - `hot_reload`: Enables [hot reloading](#hot-reloading) of the text.
+//! - `markers`: Prints [source markers](#source-markers) around the output.
+//! - `bound`: Replaces the inferred bounds of
+//!   [generic templates](#generic-templates).
+//!
+//! Flags can also be written as `cleanws = false`. Directives in the template
+//! file override these initial values.
+//!
+//! The old `#[TemplatePath = "..."]` and `#[TemplateDebug]` attributes still
+//! work but are deprecated.
+//!
+//! ## Enums
+//!
+//! An enum can have one template per variant. The fields of the variant are
+//! available as local variables in its template, fields of tuple variants are
+//! called `_0`, `_1`, ...
+//! ```rust,ignore
+//! #[derive(Template)]
+//! #[template(escape = "html")]
+//! enum Notification {
+//!     #[template(path = "./templates/welcome.tt")]
+//!     Welcome { name: String },
+//!     #[template(path = "./templates/invoice.tt")]
+//!     Invoice(u32, Vec<Item>),
+//! }
+//! ```
+//!
+//! Options on the enum itself are the defaults for all variants.
+//!
+//! ## Named templates
+//!
+//! A type can have more templates besides the one for `Display`. Each one is
+//! declared with a `name` and gets an `as_<name>()` method, which returns a
+//! `Display` adaptor, and a `render_<name>()` method, which returns a `String`:
+//! ```rust,ignore
+//! #[derive(Template)]
+//! #[template(path = "./templates/mail.txt.tt")]
+//! #[template(name = "html", path = "./templates/mail.html.tt")]
+//! #[template(name = "subject", path = "./templates/subject.tt")]
+//! struct Mail { /* ... */ }
+//!
+//! let html = mail.render_html();
+//! println!("{}", mail.as_subject());
+//! ```
+//!
+//! The options of the unnamed template are the defaults for the named ones. If
+//! there is no unnamed template, no `Display` implementation is generated.
+//!
+//! ## Generic templates
+//!
+//! The `Display` implementation of a generic type requires `T: Display` for
+//! each type parameter `T` whose values the template prints, e.g. with
+//! `<#= self.value #>` for a field `value: T` or `<#= item #>` in
+//! `<# for item in &self.items { #>` for a field `items: Vec<T>`. Other
+//! expressions, like `self.items.len()`, add no bound, and type parameters with
+//! bounds of their own are left alone. The `bound` option replaces the inferred
+//! bounds of the template, e.g. if it prints a value through a method call:
+//! ```rust,ignore
+//! #[derive(Template)]
+//! #[template(path = "./templates/first.tt", bound = "T: Display")]
+//! struct First<T> { items: Vec<T> }
+//! ```
+//! Each template has its own bounds: the bounds of a named template only apply to
+//! its methods, and the bounds of a variant are added to those of the other
+//! variants.
+//!
+//! ## Inline templates
+//!
+//! Short templates can be written inline with the `t4!` macro. It returns a
+//! `Display` value and can use the local variables of the caller:
+//! ```rust,ignore
+//! use t4rust_derive::t4;
+//!
+//! let name = "Splamy";
+//! let greeting = t4!("Dear <#= name #>,").to_string();
+//! ```
+//!
+//! The crate-wide defaults apply to inline templates as well.
+//!
+//! ## Formatting flags
+//!
+//! Width, fill and alignment of the format string apply to the whole output
+//! of a template, e.g. `format!("{:>40}", page)` right-aligns it. The
+//! alternate flag `{:#}` is available to the template code as `pretty`, so
+//! one template can render a compact and a pretty variant:
+//! ```text
+//! <#= self.name #><# if pretty { #> (<#= self.id #>)<# } #>
+//! ```
+//! Inline templates do not define `pretty`, so they can use a `pretty` variable of
+//! the caller.
+//!
+//! ## Template parameters
+//!
+//! A template can declare its inputs with `parameter` directives, a `default`
+//! makes the parameter optional:
+//! ```text
+//! <#@ parameter name="title" type="String" #>
+//! <#@ parameter name="rows" type="Vec<Row>" #>
+//! <#@ parameter name="footer" type="&'static str" default="\"-- end --\"" #>
+//! ```
+//!
+//! `template_struct!` generates a struct with these fields for the template. It
+//! is named after the file or explicitly:
+//! ```rust,ignore
+//! use t4rust_derive::template_struct;
+//!
+//! template_struct!("./templates/report.tt");
+//! template_struct!(pub(crate) MonthlyReport, "./templates/report.tt");
+//!
+//! let report = Report::builder()
+//!     .title("Sales")
+//!     .rows(rows)
+//!     .build()?;
+//! ```
+//!
+//! Fields are set directly or with the builder, `build()` returns the name of a
+//! missing parameter as error. If all parameters have defaults, the struct also
+//! implements `Default`.
+//!
+//! All templates of a directory can be turned into structs at once. Every file
+//! becomes a module with the struct of the template, subdirectories with
+//! templates become nested modules and hidden directories like `.git` are
+//! skipped:
+//! ```rust,ignore
+//! use t4rust_derive::templates;
+//!
+//! // templates/mail/welcome.html.tt, templates/mail/order-shipped.txt.tt
+//! templates!("./templates/mail");
+//!
+//! let mail = welcome::Welcome { name: "Splamy".into() };
+//! let mail = order_shipped::OrderShipped { items };
+//! ```
+//!
+//! Two files with the same module name, like `welcome.html.tt` and
+//! `welcome.txt.tt`, are reported as error. Changes to the template files trigger
+//! a rebuild, but Cargo does not know about the directory itself, so added or
+//! removed files are only picked up after the file with the macro changed. A
+//! build script can watch the directory instead:
+//! ```rust,ignore
+//! // build.rs
+//! fn main() {
+//!     println!("cargo:rerun-if-changed=templates/mail");
+//! }
+//! ```
+//!
+//! ## Build scripts
+//!
+//! The `t4rust-build` crate generates the code of a template ahead of time, e.g.
+//! to check it in or to debug it:
+//! ```rust,ignore
+//! // build.rs
+//! fn main() {
+//!     let out_dir = std::env::var("OUT_DIR").unwrap();
+//!     t4rust_build::compile("templates/report.tt", &out_dir).unwrap();
+//! }
+//!
+//! // src/main.rs
+//! include!(concat!(env!("OUT_DIR"), "/report.rs"));
+//! ```
+//!
+//! The generated file contains the `Display` implementation for the struct named
+//! after the template, and the struct itself if the template declares
+//! [parameters](#template-parameters). `compile_for` sets the name of the struct.
+//!
+//! ## Parser
+//!
+//! The `t4rust-parser` crate exposes the syntax tree of templates for linters,
+//! formatters and editor integrations. Text, code, expression and directive nodes
+//! carry their byte range in the source, directives their parsed parameters.
+//! Printing a parsed template reproduces its source byte for byte. The
+//! `directives` module describes the known directives and checks their parameters.
+//!
+//! ## Language server
+//!
+//! `t4rust-lsp` is a language server for `.tt` files. It reports unclosed blocks,
+//! malformed directives and unknown directives or parameters while typing,
+//! completes directive names, parameters and `true`/`false` values and shows the
+//! documentation of directives on hover. Install it with
+//! `cargo install t4rust-lsp` and register the `t4rust-lsp` binary for `.tt` files
+//! in your editor, it talks the protocol over stdin and stdout. Going to the files
+//! of `include` and `extends` directives will follow once templates support
+//! these directives.
+//!
+//! ## Formatter
+//!
+//! `t4fmt` from the `t4rust-fmt` crate formats templates in place:
+//! ```sh
+//! t4fmt templates/          # all .tt files in the directory
+//! t4fmt --check templates/  # fails if a file is not formatted, e.g. in CI
+//! ```
+//! Code and expression blocks are padded with one space (`<#= x #>`), directives
+//! are written on one line as `<#@ name key="value" #>`. Code and directive blocks
+//! alone on their line are indented by the nesting of the code around them, but
+//! only where `cleanws` removes the indentation from the output. Text is never
+//! touched and a template is left as it is if formatting would change the code
+//! it generates. The formatter is also available as a library, see
+//! `t4rust_fmt::format`.
+//!
+//! ## Checking templates
+//!
+//! `t4rust check` from the `t4rust-cli` crate finds broken templates without
+//! compiling the crate. It checks all `.tt` files of a project and the templates
+//! the `#[template(path = "...")]` attributes in `src` refer to, with their
+//! options. Every template has to parse, its directives have to be valid, the
+//! checks of the compiler have to pass and its code blocks have to form valid
+//! Rust. All problems are reported with their file and line:
+//! ```text
+//! error: Unclosed code or expression block
+//!   --> templates/page.tt:12:1
+//! ```
+//! `--format json` prints one JSON object per problem and line instead. The
+//! checks are also available as `t4rust_build::check::check`.
+//!
+//! ## Expanding templates
+//!
+//! `t4rust expand` prints the code generated for a template file, with a comment
+//! before the statements of each template line:
+//! ```text
+//! $ t4rust expand templates/item_list.html.tt --name Items
+//! ...
+//!         // line 3: <# for item in &self.items { #>
+//!         for item in &self.items {
+//!             // line 4: <li><#= item #></li>
+//!             _fmt.write_str(r#"  <li>"#)?;
+//! ```
+//! The template is compiled with the defaults of the crate that contains it, the
+//! struct is named after the file unless `--name` is given. The same output is
+//! available as `t4rust_build::expand`.
+//!
+//! ## Debug artifacts
+//!
+//! With the `debug` option or `<#@ template debug="true" #>`, the compiler writes
+//! debug artifacts to `target/t4rust/<crate>/<key>/` in the workspace (or below
+//! `CARGO_TARGET_DIR`). The key of a derived struct is the module path of its
+//! source file and its name, e.g. `pages/Page` for `Page` in `src/pages.rs`, the
+//! key of a struct generated for a template file is the template path and the
+//! name, e.g. `templates/page.tt/Page`:
+//! - `<template path>.json` contains the parts of each template of the struct
+//!   with their lines, after parsing and after each cleanup step
+//! - `code.rs` contains the pretty-printed generated code, the struct includes it
+//!   so that compiler errors point into it
+//!
+//! Structs which share a template have their own artifacts. Inline modules are
+//! not part of the key, so only debug one of the structs of the same name in the
+//! inline modules of a file at a time. Templates compiled with `template_struct!`
+//! or from build scripts write artifacts as well.
+//!
+//! ## Hot reloading
+//!
+//! With `#[template(hot_reload)]` or `hot_reload = true` in the
+//! [crate-wide defaults](#crate-wide-defaults), debug builds read the text of a
+//! template from its file on every render. Changes to the text show up without
+//! recompiling, e.g. while the program serves pages. The code, expression and
+//! directive blocks stay compiled: if they change, a warning is printed once and
+//! the compiled text is used until the crate is rebuilt. Release builds always
+//! use the compiled text.
+//!
+//! ## Source markers
+//!
+//! To find the template line which printed a piece of output, set
+//! `#[template(markers = "...")]` or `markers = "..."` in the
+//! [crate-wide defaults](#crate-wide-defaults). The marker is printed before
+//! the output of each text and expression block, `{file}` and `{line}` are
+//! replaced with its template file and line:
+//! ```rust,ignore
+//! #[derive(Template)]
+//! #[template(path = "page.html.tt", markers = "<!-- t4:{file}:{line} -->")]
+//! struct Page { /* ... */ }
+//! ```
+//! renders `<!-- t4:page.html.tt:42 --><li>`. A marker with `{output}`, e.g.
+//! `"<span title=\"{file}:{line}\">{output}</span>"`, wraps the output instead.
+//! For templates which generate code, a comment such as
+//! `"/* t4:{file}:{line} */"` works as well.
+//!
+//! ## Coverage
+//!
+//! To see which parts of the templates the tests render, enable `coverage` for the
+//! crate and add the `t4rust-coverage` crate, which the instrumented code uses:
+//! ```toml
+//! [target.'cfg(t4rust_coverage)'.dependencies]
+//! t4rust-coverage = "0.3"
+//!
+//! [package.metadata.t4rust]
+//! coverage = true
+//!
+//! [lints.rust]
+//! unexpected_cfgs = { level = "warn", check-cfg = ["cfg(t4rust_coverage)"] }
+//! ```
+//! The instrumentation is only compiled with the `t4rust_coverage` cfg, e.g. with
+//! `RUSTFLAGS="--cfg t4rust_coverage" cargo test`, other builds do not pay for it.
+//! Every text and code block of the templates of the crate then counts its
+//! renders, the templates of other crates are not instrumented. After the tests,
+//! `t4rust_coverage::lcov()` returns an lcov report for the usual coverage tools
+//! and `t4rust_coverage::html()` a page which shows the blocks that were never
+//! rendered in red. Templates which were not rendered at all are missing from the
+//! reports.
+//!
+//! ## Render errors
+//!
+//! A failing `Display` implementation only returns the opaque `fmt::Error`. With
+//! `errors` enabled for the crate, every template of the crate gets a
+//! `try_render()` method, and named templates a `try_render_<name>()` method.
+//! They return a `t4rust_error::RenderError` with the template file, the line of
+//! the block that failed and the cause, which can be the error of a nested
+//! template. The generated code uses the `t4rust-error` crate:
+//! ```toml
+//! [dependencies]
+//! t4rust-error = "0.3"
+//!
+//! [package.metadata.t4rust]
+//! errors = true
+//! ```
+//! Template code can fail with its own message with `bail!`:
+//! ```text
+//! <# let price = match self.price {
+//!     Some(price) => price,
+//!     None => bail!("missing price for {}", self.name),
+//! }; #>
+//! ```
+//!
+//! ## Crate-wide defaults
+//!
+//! Defaults for all templates of a crate can be set in its `Cargo.toml`:
+//! ```toml
+//! [package.metadata.t4rust]
+//! # Template paths are relative to this directory
+//! root = "templates"
+//! cleanws = true
+//! strict = true
+//! hot_reload = true
+//! escape = "crate::esc::html"
+//!
+//! # Escape functions by the inner extension of the template, e.g. `mail.txt.tt`
+//! [package.metadata.t4rust.extensions]
+//! txt = ""
+//! ```
+//!
+//! The `#[template(...)]` attribute and directives in the template file override
+//! these defaults.
+//!
 //! ## Auto-escaping
 //!
 //! Use the `escape` directive in your .tt file:
@@ -86,719 +443,413 @@
 //! All expression blocks (e.g. `<#= self.name #>`) will call the escape
 //! function before inserted.
 //!
+//! Instead of a function you can also use the built-in `html` or `xml` escapers
+//! with `function="html"`.
+//!
 //! You can redeclare this directive as many times and where you want in your
-//! template to change or disable (with `function=""`) the escape function.
+//! template to change or disable (with `function=""` or `function="none"`) the
+//! escape function.
+//!
+//! Templates with a content type in their file name, like `index.html.tt` or
+//! `feed.xml.tt`, use the matching built-in escaper by default. Escapers
+//! configured for the crate or in the `#[template(...)]` attribute take
+//! precedence over this default.
+//!
+//! To print a single expression without escaping, wrap it in `raw!`:
+//! ```text
+//! <#= raw!(self.rendered_html) #>
+//! ```
+//!
+//! ## Strict escaping
+//!
+//! In strict mode a template can not print unescaped output by accident. It is
+//! enabled with `#[template(strict)]`, with `strict = true` in the crate-wide
+//! defaults or with `<#@ template strict="true" #>`. Once enabled it can not be
+//! disabled again.
+//!
+//! In strict mode
+//! - every expression block needs an escape function or the `raw!` marker,
+//! - the escape function can not be disabled with `function=""`,
+//! - code blocks can not write to the formatter with `out!` or `_fmt`.
+//!
+//! ## Restricted templates
+//!
+//! Templates which are edited by people who should not write arbitrary Rust code
+//! can be restricted to text and expression blocks with
+//! `#[template(restricted)]`. Simple control flow can be allowed with
+//! `#[template(allow = "if, for")]`, code blocks may then only contain headers
+//! like `if cond {`, `} else if cond {`, `} else {`, `for x in iter {` and the
+//! closing `}`. Expressions, conditions and iterators may only use fields,
+//! literals, operators and method calls, blocks, closures, function calls and
+//! macros are rejected.
 
 extern crate proc_macro;
 
-use std::collections::hash_map::DefaultHasher;
-use std::fs::File;
-use std::hash::Hasher;
-use std::io::prelude::*;
-use std::option::Option;
+mod bounds;
+mod directory;
+
 use std::path::Path;
 use std::path::PathBuf;
-use std::result::Result;
-use std::vec::Vec;
 
-use nom::{
-	branch::alt,
-	bytes::complete::{
-		escaped_transform, is_not, tag, take, take_until, take_while,
-	},
-	character::complete::{alphanumeric1, line_ending, space0},
-	combinator::{map, not, opt, peek},
-	multi::many0,
-	sequence::tuple,
-	IResult,
-};
-use quote::quote;
-use syn::Meta::*;
+use quote::{format_ident, quote};
 use syn::*;
+use t4rust_build::attributes::{TemplateAttributes, TEMPLATE_ATTRIBUTE};
+use t4rust_build::config::CrateConfig;
+use t4rust_build::{
+	debug, formatter_ident, parameters, template_body, template_view,
+	try_render_method, CompiledTemplate,
+};
 
-use crate::TemplatePart::*;
-
-macro_rules! dbg_println {
-	($inf:ident) => { if $inf.debug_print { println!(); } };
-	($inf:ident, $fmt:expr) => { if $inf.debug_print { println!($fmt); } };
-	($inf:ident, $fmt:expr, $($arg:tt)*) => { if $inf.debug_print { println!($fmt, $($arg)*); } };
-}
-
-macro_rules! dbg_print {
-	($inf:ident) => { if $inf.debug_print { print!(); } };
-	($inf:ident, $fmt:expr) => { if $inf.debug_print { print!($fmt); } };
-	($inf:ident, $fmt:expr, $($arg:tt)*) => { if $inf.debug_print { print!($fmt, $($arg)*); } };
-}
-
-const TEMPLATE_PATH_MACRO: &str = "TemplatePath";
-const TEMPLATE_DEBUG_MACRO: &str = "TemplateDebug";
-
-#[proc_macro_derive(Template, attributes(TemplatePath, TemplateDebug))]
+#[proc_macro_derive(Template, attributes(template, TemplatePath, TemplateDebug))]
 pub fn transform_template(
 	input: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
 	let macro_input = parse_macro_input!(input as DeriveInput);
 
-	let mut path: Option<String> = None;
-	let mut info = TemplateInfo::default();
+	let config = crate_config();
+	let attributes =
+		TemplateAttributes::parse(&macro_input.attrs, config.info())
+			.unwrap_or_else(|err| panic!("{}", err));
+	let named = attributes
+		.named(&macro_input.attrs)
+		.unwrap_or_else(|err| panic!("{}", err));
+	let mut warnings = attributes.warnings.clone();
+	let mut debug_print = false;
+	let mut stages = Vec::new();
+	// The bounds of the `Display` implementation, inferred for every template
+	// without a `bound` option
+	let mut display_bounds = attributes.bound.clone().unwrap_or_default();
+	let generics = &macro_input.generics;
+	let scope = match &macro_input.data {
+		Data::Struct(data) => bounds::Scope::of_fields(&data.fields),
+		Data::Union(data) => bounds::Scope::of_fields(&data.fields.named),
+		Data::Enum(_) => bounds::Scope::default(),
+	};
 
-	for attr in macro_input.attrs {
-		let meta = attr.parse_meta().expect("Failed to parse t4 attribute");
-		match &meta {
-			NameValue(MetaNameValue {
-				path: p,
-				lit: Lit::Str(lit_str),
-				..
-			}) => {
-				if p.get_ident().expect("Attribute with no name")
-					== TEMPLATE_PATH_MACRO
-				{
-					path = Some(lit_str.value());
+	let body = match &macro_input.data {
+		Data::Enum(_) if !named.is_empty() => {
+			panic!("Named templates are not supported for enums")
+		}
+		Data::Enum(data) => {
+			// Each variant has its own template, the attributes of the enum
+			// are the defaults for all variants.
+			let mut arms = Vec::new();
+			for variant in &data.variants {
+				let variant_attributes = attributes
+					.inherit(&variant.attrs)
+					.unwrap_or_else(|err| panic!("{}", err));
+				warnings.extend(variant_attributes.warnings.clone());
+				if variant_attributes.path.is_none() {
+					panic!(
+						"Please specify a #[{}(path = \"<path>\")] atribute \
+						 for the variant {}.",
+						TEMPLATE_ATTRIBUTE, variant.ident
+					);
 				}
+				let bound = variant_attributes.bound.clone();
+				let template = compile_template(&config, variant_attributes);
+				debug_print |= template.debug_print;
+				stages.extend(template.stages);
+				// The bound of the enum replaces the inferred bounds of all
+				// variants
+				display_bounds.extend(match bound {
+					Some(predicates) => predicates,
+					None if attributes.bound.is_some() => Vec::new(),
+					None => bounds::display(
+						generics,
+						&bounds::Scope::of_variant(variant),
+						&template.blocks,
+					),
+				});
+
+				let ident = &variant.ident;
+				let tokens = template.tokens;
+				let pattern = match &variant.fields {
+					Fields::Named(fields) => {
+						let names = fields.named.iter().map(|f| &f.ident);
+						quote! { { #(#names),* } }
+					}
+					Fields::Unnamed(fields) => {
+						let names = (0..fields.unnamed.len()).map(|i| {
+							proc_macro2::Ident::new(
+								&format!("_{}", i),
+								proc_macro2::Span::call_site(),
+							)
+						});
+						quote! { ( #(#names),* ) }
+					}
+					Fields::Unit => quote! {},
+				};
+				arms.push(quote! {
+					#[allow(unused_variables)]
+					Self::#ident #pattern => { #tokens }
+				});
 			}
-			Path(name) => {
-				if name.get_ident().expect("Attribute with no name")
-					== TEMPLATE_DEBUG_MACRO
-				{
-					info.debug_print = true;
+			Some(quote! {
+				match self {
+					#(#arms)*
 				}
+			})
+		}
+		// With named templates the `Display` implementation is optional
+		_ if attributes.path.is_none() && !named.is_empty() => None,
+		_ => {
+			let bound = attributes.bound.is_some();
+			let template = compile_template(&config, attributes);
+			debug_print = template.debug_print;
+			stages.extend(template.stages);
+			if !bound {
+				display_bounds =
+					bounds::display(generics, &scope, &template.blocks);
 			}
-			_ => {}
+			Some(template.tokens)
 		}
-	}
-
-	// Get template path
-	let mut path_absolute =
-		PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
-	path_absolute.push(&path.unwrap_or_else(|| {
-		panic!(
-			"Please specify a #[{}=\"<path>\"] atribute with the template \
-			 file path.",
-			TEMPLATE_PATH_MACRO
-		)
-	}));
-	let path =
-		&path_absolute.canonicalize().expect("Could not canonicalize path");
-	dbg_println!(
-		info,
-		"Looking for template in \"{}\"",
-		path.to_str().unwrap()
-	);
-
-	// Read template file
-	let read = read_from_file(path).expect("Could not read file");
+	};
 
-	// Parse template file
-	let mut data = parse_all(&mut info, &read).expect("Parse failed!");
+	// Build frame and insert
+	let name = &macro_input.ident;
+	let vis = &macro_input.vis;
+
+	// Generates an implementation for the type with additional bounds,
+	// `trait_` is e.g. `Display for`
+	let implement = |trait_, predicates, items| {
+		let mut generics = generics.clone();
+		bounds::add(&mut generics, predicates);
+		let (impl_generics, ty_generics, where_clause) =
+			generics.split_for_impl();
+		quote! {
+			impl #impl_generics #trait_ #name #ty_generics #where_clause {
+				#items
+			}
+		}
+	};
 
-	if info.debug_print {
-		debug_to_file(path, &data);
+	// Every template has its own bounds, so the methods of each template are
+	// in an implementation of their own
+	let mut methods = Vec::new();
+	if body.is_some() && config.errors {
+		let method = format_ident!("try_render");
+		let method = try_render_method(vis, &method, quote! { self });
+		methods.push(implement(quote! {}, display_bounds.clone(), method));
 	}
 
-	parse_postprocess(&mut data);
-
-	let data = parse_optimize(data);
-
-	// Build code from template
-	info = TemplateInfo::default();
-	let mut builder = String::new();
-	for part in data {
-		match part {
-			Text(x) => {
-				builder.push_str(generate_save_str_print(&x).as_ref());
-			}
-			Code(x) => {
-				builder.push_str(x.as_ref());
-			}
-			Expr(x) => {
-				builder.push_str(generate_expression_print(&x, &info).as_ref());
+	// Every named template gets a method returning a `Display` adaptor and a
+	// method rendering it into a string.
+	for template_attributes in named {
+		let template_name = template_attributes.name.clone().unwrap();
+		if template_attributes.path.is_none() {
+			panic!(
+				"Please specify a path for the template \"{}\".",
+				template_name
+			);
+		}
+		warnings.extend(template_attributes.warnings.clone());
+		let bound = template_attributes.bound.clone();
+		let template = compile_template(&config, template_attributes);
+		let predicates = bound.unwrap_or_else(|| {
+			bounds::display(generics, &scope, &template.blocks)
+		});
+		debug_print |= template.debug_print;
+		stages.extend(template.stages);
+
+		let as_name = format_ident!("as_{}", template_name);
+		let render_name = format_ident!("render_{}", template_name);
+		let view = template_view(template.tokens, quote! { move }, true);
+		let mut items = quote! {
+			#[allow(dead_code)]
+			#vis fn #as_name(&self) -> impl ::std::fmt::Display + '_ {
+				#view
 			}
-			Directive(dir) => {
-				apply_directive(&mut info, &dir);
+
+			#[allow(dead_code)]
+			#vis fn #render_name(&self) -> ::std::string::String {
+				::std::string::ToString::to_string(&self.#as_name())
 			}
+		};
+		if config.errors {
+			let method = format_ident!("try_render_{}", template_name);
+			let view = quote! { self.#as_name() };
+			items.extend(try_render_method(vis, &method, view));
 		}
+		methods.push(implement(quote! {}, predicates, items));
 	}
 
-	dbg_println!(info, "Generated Code:\n{}", builder);
-
-	let tokens: proc_macro2::TokenStream =
-		builder.parse().expect("Parsing template code failed!");
-
-	// Build frame and insert
-	let (impl_generics, ty_generics, where_clause) =
-		macro_input.generics.split_for_impl();
-	let name = &macro_input.ident;
-	let path_str = path.to_str().expect("Invalid path");
+	let fmt = formatter_ident();
+	let display = body.map(|body| {
+		let body = match &macro_input.data {
+			// A reference to an empty enum can not be matched without
+			// dereferencing it, and nothing can be rendered after the match
+			Data::Enum(data) if data.variants.is_empty() => {
+				quote! { match *self {} }
+			}
+			_ => template_body(body),
+		};
+		implement(
+			quote! { ::std::fmt::Display for },
+			display_bounds,
+			quote! {
+				fn fmt(&self, #fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+					#body
+				}
+			},
+		)
+	});
 
 	let frame = quote! {
-		impl #impl_generics ::std::fmt::Display for #name #ty_generics #where_clause {
-			fn fmt(&self, _fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-				let _ = include_bytes!(#path_str);
-				#tokens
-				Ok(())
-			}
-		}
+		#display
+		#(#methods)*
+		#warnings
 	};
 
 	// We could return the code now. The problem is that span information are
 	// missing and the error messages are awful.
 	// So instead, we write to a file and include! this file, which still does
 	// not give us nice errors but at least includes source code.
-	if !info.debug_print {
+	if !debug_print {
 		proc_macro::TokenStream::from(frame)
 	} else {
-		// Unfortunately we have no access to OUT_DIR like build scripts so we
-		// try to emulate that partially.
-
-		// Use hash of template path as filename
-		let mut hasher = DefaultHasher::new();
-		hasher.write(path_str.as_bytes());
-
-		let out_dir = if let Ok(target_dir) = std::env::var("CARGO_TARGET_DIR")
-		{
-			PathBuf::from(target_dir)
-		} else {
-			let dir = std::env::var("CARGO_MANIFEST_DIR")
-				.expect("CARGO_MANIFEST_DIR not set");
-			PathBuf::from(dir).join("target")
-		};
-
-		let code_path = out_dir
-			.join("t4rust")
-			.join(&hasher.finish().to_string())
-			.with_extension("rs");
-
-		std::fs::create_dir_all(code_path.parent().unwrap())
-			.expect("Failed to create output path");
-
-		// Write file
-		std::fs::write(&code_path, frame.to_string().as_bytes())
-			.expect("Failed to write compiled template");
-
+		// Structs of the same name in different modules need their own
+		// artifacts, so the key contains the module of the source file
+		let span = name.span().unwrap();
+		let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+		let key = debug::item_key(
+			Path::new(&manifest_dir),
+			&span.local_file().unwrap_or_else(|| PathBuf::from(span.file())),
+			&name.to_string(),
+		);
+		let code_path =
+			t4rust_build::write_artifacts(&config, &key, &stages, &frame)
+				.unwrap_or_else(|err| panic!("{}", err));
 		let code_path_str = code_path.to_str();
 		proc_macro::TokenStream::from(quote! { include!(#code_path_str); })
 	}
 }
 
-fn generate_expression_print(print_expr: &str, info: &TemplateInfo) -> String {
-	if info.print_postprocessor.is_empty() {
-		format!("write!(_fmt, \"{{}}\", {})?;\n", print_expr)
-	} else {
-		format!(
-			"{{
-			let _s = format!(\"{{}}\", {});
-			let _s_transfomed = {}(&_s);
-			_fmt.write_str(&_s_transfomed)?;
-			}}\n",
-			print_expr, info.print_postprocessor
-		)
+/// Renders an inline template, the local variables of the caller can be used
+/// in it.
+///
+/// ```
+/// use t4rust_derive::t4;
+///
+/// let name = "Splamy";
+/// let text = t4!("Dear <#= name #>,").to_string();
+/// assert_eq!(text, "Dear Splamy,");
+/// ```
+#[proc_macro]
+pub fn t4(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+	let source = parse_macro_input!(input as LitStr);
+
+	let config = crate_config();
+	let mut info = config.info();
+	if let Some(escape) = &config.escape {
+		info.print_postprocessor = escape.clone();
 	}
-}
-
-fn generate_save_str_print(print_str: &str) -> String {
-	let mut max_sharp_count = 0;
-	let mut cur_sharp_count = 0;
 
-	for c in print_str.chars() {
-		if c == '#' {
-			cur_sharp_count += 1;
-			max_sharp_count = std::cmp::max(max_sharp_count, cur_sharp_count);
-		} else {
-			cur_sharp_count = 0;
-		}
-	}
-
-	let sharps = "#".repeat(max_sharp_count + 1);
-	format!("_fmt.write_str(r{1}\"{0}\"{1})?;\n", print_str, sharps)
-}
-
-fn read_from_file(path: &Path) -> Result<String, std::io::Error> {
-	let mut file = File::open(path)?;
-	let mut contents = String::new();
-	file.read_to_string(&mut contents)?;
-	Ok(contents)
-}
-
-fn debug_to_file(path: &Path, data: &[TemplatePart]) {
-	let mut pathbuf = PathBuf::new();
-	pathbuf.push(path);
-	pathbuf.set_extension("tt.out");
-	let writepath = pathbuf.as_path();
-	if let Ok(mut file) = File::create(writepath) {
-		for var in data {
-			match *var {
-				Code(ref x) => {
-					write!(file, "Code:").unwrap();
-					file.write_all(x.as_bytes()).unwrap();
-				}
-				Text(ref x) => {
-					write!(file, "Text:").unwrap();
-					file.write_all(x.as_bytes()).unwrap();
-				}
-				Expr(ref x) => {
-					write!(file, "Expr:").unwrap();
-					file.write_all(x.as_bytes()).unwrap();
-				}
-				Directive(ref dir) => {
-					write!(file, "Dir:{:?}", dir).unwrap();
-				}
-			}
-			writeln!(file).unwrap();
-		}
-	}
+	let template = t4rust_build::compile_source(&info, &source.value(), None)
+		.unwrap_or_else(|err| panic!("{}", err));
+	proc_macro::TokenStream::from(template_view(
+		template.tokens,
+		quote! {},
+		false,
+	))
 }
 
-/// Transforms template code into an intermediate representation
-fn parse_all(
-	info: &mut TemplateInfo,
-	input: &str,
-) -> Result<Vec<TemplatePart>, TemplateError>
-{
-	let mut builder: Vec<TemplatePart> = Vec::new();
-	let mut cur = input;
-
-	dbg_println!(info, "Reading template");
-
-	while !cur.is_empty() {
-		let (crest, content) = parse_text(info, cur)?;
-		builder.push(Text(content));
-		cur = crest;
-		dbg_println!(info, "");
-
-		// Read code block
-		if let Ok((rest, _)) = expression_start(cur) {
-			dbg_print!(info, " expression start");
-			let (crest, content) = parse_code(info, rest)?;
-			builder.push(Expr(content));
-			cur = crest;
-		} else if let Ok((rest, _)) = template_directive_start(cur) {
-			dbg_print!(info, " directive start");
-			let (crest, content) = parse_code(info, rest)?;
-			let dir = parse_directive(&content);
-			dbg_println!(info, " Directive: {:?}", dir);
-			match dir {
-				Ok((_, dir)) => {
-					apply_directive(info, &dir);
-					builder.push(Directive(dir));
-				}
-				Err(_) => {
-					println!("Malformed directive: {}", &content);
-					return Err(TemplateError {
-						index: 0,
-						reason: format!(
-							"Could not understand the directive: {}",
-							&content
-						),
-					});
-				}
-			}
-			cur = crest;
-		} else if let Ok((rest, _)) = code_start(cur) {
-			dbg_print!(info, " code start");
-			let (crest, content) = parse_code(info, rest)?;
-			builder.push(Code(content));
-			cur = crest;
-		}
-
-		dbg_println!(info, " Rest: {:?}", &cur);
-	}
-
-	dbg_println!(info, "\nTemplate ok!");
-
-	Result::Ok(builder)
-}
-
-fn parse_text<'a>(
-	info: &TemplateInfo,
-	input: &'a str,
-) -> Result<(&'a str, String), TemplateError>
-{
-	let mut content = String::new();
-	let mut cur = input;
-
-	loop {
-		let read = read_text(cur);
-		match read {
-			Ok((rest, done)) => {
-				content.push_str(&done);
-				if rest.is_empty() {
-					return Ok((rest, content));
-				}
-				cur = rest;
-				dbg_print!(info, " take text: {:?}", &done);
-
-				if let Ok((rest, _)) = double_code_start(cur) {
-					dbg_print!(info, " double-escape");
-					content.push_str("<#");
-
-					if rest.is_empty() {
-						return Ok((rest, content));
-					}
-					cur = rest;
-				} else if done.is_empty() {
-					return Ok((rest, content));
-				}
-			}
-			Err(_) => {
-				if let Ok((rest, done)) = till_end(cur) {
-					if rest.is_empty() {
-						content.push_str(&done);
-						return Ok((rest, content));
-					}
-				}
-				panic!(
-					"Reached unknown parsing state (!read_text > !till_end)"
-				);
-			}
-		}
-
-		dbg_println!(info, " Rest: {:?}", &cur);
-	}
-}
-
-fn parse_code<'a>(
-	info: &TemplateInfo,
-	input: &'a str,
-) -> Result<(&'a str, String), TemplateError>
-{
-	let mut content = String::new();
-	let mut cur = input;
-
-	loop {
-		match read_code(cur) {
-			Ok((rest, done)) => {
-				dbg_print!(info, " take code: {:?}", &done);
-				content.push_str(&done);
-				cur = rest;
-
-				if let Ok((rest, _)) = code_end(cur) {
-					dbg_print!(info, " code end");
-					return Ok((rest, content));
-				} else if let Ok((rest, _)) = double_code_end(cur) {
-					dbg_print!(info, " double-escape");
-					content.push_str("#>");
-					cur = rest;
-				} else {
-					panic!("Nothing, i guess?");
-				}
-			}
-			Err(err) => {
-				dbg_println!(info, "Error at code {:?}", err);
-				return Err(TemplateError {
-					index: 0,
-					reason: "Unclosed code or expression block".into(),
-				});
-			}
-		}
-	}
+/// Generates a struct for a template file from its `parameter` directives.
+///
+/// The struct is named after the file, `report.tt` becomes `Report`, or can
+/// be named explicitly:
+/// ```rust,ignore
+/// template_struct!("templates/report.tt");
+/// template_struct!(pub(crate) MonthlyReport, "templates/report.tt");
+/// ```
+#[proc_macro]
+pub fn template_struct(
+	input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+	let input = parse_macro_input!(input as TemplateStructInput);
+
+	let config = crate_config();
+	let path = input.path.value();
+	let name = input.name.unwrap_or_else(|| {
+		parameters::type_name(Path::new(&path)).unwrap_or_else(|| {
+			panic!("Can not derive a struct name from \"{}\"", path)
+		})
+	});
+	let vis = input.vis.unwrap_or_else(|| parse_quote! { pub });
+
+	proc_macro::TokenStream::from(
+		t4rust_build::template_struct(&config, &vis, &name, path.as_ref())
+			.unwrap_or_else(|err| panic!("{}", err)),
+	)
 }
 
-/// Merges multiple identical Parts into one
-fn parse_optimize(data: Vec<TemplatePart>) -> Vec<TemplatePart> {
-	let mut last_type = TemplatePartType::None;
-	let mut combined = Vec::<TemplatePart>::new();
-	let mut tmp_build = String::new();
-	for item in data {
-		match item {
-			Code(u) => {
-				if u.is_empty() {
-					continue;
-				}
-				if last_type != TemplatePartType::Code {
-					if !tmp_build.is_empty() {
-						match last_type {
-							TemplatePartType::None | TemplatePartType::Code => {
-								panic!()
-							}
-							TemplatePartType::Text => {
-								combined.push(Text(tmp_build))
-							}
-							TemplatePartType::Expr => {
-								combined.push(Expr(tmp_build))
-							}
-						}
-					}
-					tmp_build = String::new();
-					last_type = TemplatePartType::Code;
-				}
-				tmp_build.push_str(&u);
-			}
-			Text(u) => {
-				if u.is_empty() {
-					continue;
-				}
-				if last_type != TemplatePartType::Text {
-					if !tmp_build.is_empty() {
-						match last_type {
-							TemplatePartType::None | TemplatePartType::Text => {
-								panic!()
-							}
-							TemplatePartType::Code => {
-								combined.push(Code(tmp_build))
-							}
-							TemplatePartType::Expr => {
-								combined.push(Expr(tmp_build))
-							}
-						}
-					}
-					tmp_build = String::new();
-					last_type = TemplatePartType::Text;
-				}
-				tmp_build.push_str(&u);
-			}
-			Expr(u) => {
-				if !tmp_build.is_empty() {
-					match last_type {
-						TemplatePartType::None => panic!(),
-						TemplatePartType::Code => {
-							combined.push(Code(tmp_build))
-						}
-						TemplatePartType::Text => {
-							combined.push(Text(tmp_build))
-						}
-						TemplatePartType::Expr => {
-							combined.push(Expr(tmp_build))
-						}
-					}
-				}
-				tmp_build = String::new();
-				last_type = TemplatePartType::Expr;
-				tmp_build.push_str(&u);
-			}
-			Directive(d) => {
-				combined.push(Directive(d));
-			}
-		}
-	}
-	if !tmp_build.is_empty() {
-		match last_type {
-			TemplatePartType::None => {}
-			TemplatePartType::Code => combined.push(Code(tmp_build)),
-			TemplatePartType::Text => combined.push(Text(tmp_build)),
-			TemplatePartType::Expr => combined.push(Expr(tmp_build)),
-		}
-	}
-	combined
+/// Generates a module with a template struct for every template file in a
+/// directory, see [`template_struct!`].
+///
+/// ```rust,ignore
+/// templates!("templates/mail");
+///
+/// let mail = welcome::Welcome { name: "Splamy".into() };
+/// ```
+///
+/// Added template files are not picked up automatically, see the
+/// [crate documentation](crate#template-parameters).
+#[proc_macro]
+pub fn templates(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+	let dir = parse_macro_input!(input as LitStr);
+
+	let config = crate_config();
+
+	proc_macro::TokenStream::from(directory::generate_modules(
+		&config,
+		Path::new(&dir.value()),
+	))
 }
 
-/// Applies template directives like 'cleanws' and modifies the input
-/// accordingly.
-fn parse_postprocess(data: &mut Vec<TemplatePart>) {
-	let mut info = TemplateInfo::default();
-	let mut was_b_clean = None;
-	let mut clean_index = 0;
-
-	// if there are less than 3 blocks available we can't do any transformations
-	if data.len() < 3 {
-		return;
-	}
-
-	for i in 0..(data.len() - 2) {
-		let tri = data[i..(i + 3)].as_mut();
-		if let Directive(ref dir) = tri[1] {
-			apply_directive(&mut info, dir);
-		}
-
-		if !info.clean_whitespace
-			|| !tri[0].is_text()
-			|| !tri[1].should_trim_whitespace()
-			|| !tri[2].is_text()
-		{
-			continue;
-		}
-
-		let mut res_a = None;
-		if clean_index == i && was_b_clean.is_some() {
-			res_a = was_b_clean;
-		} else if let Text(ref text_a) = tri[0] {
-			let rev_txt: String = text_a.chars().rev().collect();
-			if let Ok((_, a_len)) = is_ws_till_newline(&rev_txt) {
-				res_a = Some(a_len);
-			} else if i == 0 && text_a.is_empty() {
-				// Start of file
-				res_a = Some((0, 0));
-			} else {
-				continue;
-			}
-		}
-
-		let mut res_b = None;
-		if let Text(ref text_b) = tri[2] {
-			if let Ok((_, b_len)) = is_ws_till_newline(&text_b) {
-				res_b = Some(b_len);
-			} else {
-				continue;
-			}
-		}
-
-		// start trimming
-
-		if let Text(ref mut text_a) = tri[0] {
-			let res_a = res_a.unwrap();
-			let len = text_a.len();
-			text_a.drain((len - (res_a.0))..len);
-		}
-
-		if let Text(ref mut text_b) = tri[2] {
-			let rev_txt: String = text_b.chars().rev().collect();
-			if let Ok((_, b_len)) = is_ws_till_newline(&rev_txt) {
-				was_b_clean = Some(b_len);
-				clean_index = i + 2;
-			}
-
-			let res_b = res_b.unwrap();
-			text_b.drain(0..(res_b.0 + res_b.1));
-		}
-	}
+/// The input of `template_struct!`, `[vis Name,] "path"`.
+struct TemplateStructInput {
+	vis: Option<Visibility>,
+	name: Option<Ident>,
+	path: LitStr,
 }
 
-fn apply_directive(info: &mut TemplateInfo, directive: &TemplateDirective) {
-	for (key, value) in directive
-		.params
-		.iter()
-		.map(|p| ((directive.name.as_str(), p.0.as_str()), p.1.as_str()))
-	{
-		match key {
-			("template", "debug") => {
-				info.debug_print = value.parse::<bool>().unwrap()
-			}
-			("template", "cleanws") | ("template", "clean_whitespace") => {
-				info.clean_whitespace = value.parse::<bool>().unwrap()
-			}
-			("escape", "function") => {
-				info.print_postprocessor = value.to_string()
-			}
-			_ => println!(
-				"Unrecognized template parameter \"{}\" in \"{}\"",
-				key.0, key.1
-			),
+impl parse::Parse for TemplateStructInput {
+	fn parse(input: parse::ParseStream) -> syn::Result<Self> {
+		if input.peek(LitStr) {
+			return Ok(Self { vis: None, name: None, path: input.parse()? });
 		}
+		let vis = input.parse()?;
+		let name = input.parse()?;
+		input.parse::<Token![,]>()?;
+		Ok(Self { vis: Some(vis), name: Some(name), path: input.parse()? })
 	}
 }
 
-// NOM DECLARATIONS ===========================================================
-
-fn expression_start(s: &str) -> IResult<&str, &str> { tag("<#=")(s) }
-fn template_directive_start(s: &str) -> IResult<&str, &str> { tag("<#@")(s) }
-fn read_text(s: &str) -> IResult<&str, &str> { take_until("<#")(s) }
-
-fn code_start(s: &str) -> IResult<&str, &str> {
-	let (s, r) = tag("<#")(s)?;
-	not(tag("<#"))(s)?;
-	Ok((s, r))
-}
-fn double_code_start(s: &str) -> IResult<&str, &str> { tag("<#<#")(s) }
-
-fn code_end(s: &str) -> IResult<&str, &str> {
-	let (s, r) = tag("#>")(s)?;
-	not(tag("#>"))(s)?;
-	Ok((s, r))
-}
-fn double_code_end(s: &str) -> IResult<&str, &str> { tag("#>#>")(s) }
-
-fn read_code(s: &str) -> IResult<&str, &str> { take_until("#>")(s) }
-
-fn till_end(s: &str) -> IResult<&str, &str> { take_while(|_| true)(s) }
-
-fn parse_directive(s: &str) -> IResult<&str, TemplateDirective> {
-	map(
-		tuple((space0, alphanumeric1, many0(parse_directive_param), at_end)),
-		|t| TemplateDirective { name: t.1.to_string(), params: t.2 },
-	)(s)
-}
-
-fn at_end(s: &str) -> IResult<&str, ()> { not(peek(take(1usize)))(s) }
-
-fn parse_directive_param(s: &str) -> IResult<&str, (String, String)> {
-	map(
-		tuple((
-			space0,
-			alphanumeric1,
-			space0,
-			tag("="),
-			space0,
-			tag("\""),
-			opt(escaped_transform(
-				is_not("\\\""),
-				'\\',
-				alt((tag_transform("\\", "\\"), tag_transform("\"", "\""))),
-			)),
-			tag("\""),
-			space0,
-		)),
-		|t| (t.1.to_string(), t.6.unwrap_or_else(|| "".to_string())),
-	)(s)
-}
-
-fn is_ws_till_newline(s: &str) -> IResult<&str, (usize, usize)> {
-	map(
-		tuple((space0, line_ending)),
-		|t: (&str, &str)| (t.0.len(), t.1.len()),
-	)(s)
+/// The configuration of the crate which uses the macro.
+fn crate_config() -> CrateConfig {
+	let manifest_dir =
+		PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+	CrateConfig::load(&manifest_dir).unwrap_or_else(|err| panic!("{}", err))
 }
 
-fn tag_transform<'a>(
-	s: &'a str,
-	t: &'a str,
-) -> impl Fn(&'a str) -> IResult<&str, &str>
+/// Reads, parses and generates the code for one template file.
+fn compile_template(
+	config: &CrateConfig,
+	attributes: TemplateAttributes,
+) -> CompiledTemplate
 {
-	move |i: &'a str| {
-		let (r, _) = tag(s)(i)?;
-		Ok((r, t))
-	}
-}
-
-// NOM END ====================================================================
-
-#[derive(Debug)]
-struct TemplateError {
-	reason: String,
-	index: usize,
-}
-
-#[derive(Debug)]
-struct TemplateDirective {
-	name: String,
-	params: Vec<(String, String)>,
-}
-
-#[derive(Debug)]
-enum TemplatePart {
-	Text(String),
-	Code(String),
-	Expr(String),
-	Directive(TemplateDirective),
-}
-
-impl TemplatePart {
-	fn is_text(&self) -> bool { matches!(self, Text(_)) }
-
-	/// Whitespace should only be trimmed for code and directive blocks, we want to keep it for
-	/// expressions.
-	fn should_trim_whitespace(&self) -> bool { matches!(self, Code(_) | Directive(_)) }
-}
-
-#[derive(PartialEq)]
-enum TemplatePartType {
-	None,
-	Code,
-	Text,
-	Expr,
-}
-
-#[derive(Debug)]
-struct TemplateInfo {
-	debug_print: bool,
-	clean_whitespace: bool,
-	print_postprocessor: String,
-}
-
-impl TemplateInfo {
-	fn default() -> Self {
-		Self {
-			debug_print: false,
-			clean_whitespace: false,
-			print_postprocessor: "".into(),
-		}
-	}
+	let path = attributes.path.unwrap_or_else(|| {
+		panic!(
+			"Please specify a #[{}(path = \"<path>\")] atribute with the \
+			 template file path.",
+			TEMPLATE_ATTRIBUTE
+		)
+	});
+	t4rust_build::compile_file(
+		config,
+		attributes.info,
+		attributes.escape.as_deref(),
+		Path::new(&path.value()),
+	)
+	.unwrap_or_else(|err| panic!("{}", err))
 }
@@ -0,0 +1,357 @@
+//! Reads the configuration attributes of a template type.
+//!
+//! The derive macro reports the errors, tools which only look for templates
+//! can skip the attributes they can not read.
+
+use proc_macro2::TokenStream;
+use quote::quote_spanned;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::{Attribute, Lit, LitStr, Meta, NestedMeta, Token, WherePredicate};
+
+use crate::policy::Construct;
+use crate::TemplateInfo;
+
+pub const TEMPLATE_ATTRIBUTE: &str = "template";
+pub const TEMPLATE_PATH_MACRO: &str = "TemplatePath";
+pub const TEMPLATE_DEBUG_MACRO: &str = "TemplateDebug";
+
+/// The options of one template, collected from all attributes.
+pub struct TemplateAttributes {
+	/// The name of an additional template, `None` for the template of the
+	/// `Display` implementation.
+	pub name: Option<String>,
+	/// The template file, the span of the literal locates the attribute.
+	pub path: Option<LitStr>,
+	/// The initial settings, directives in the template file override them.
+	pub info: TemplateInfo,
+	/// The escape function, it overrides the crate defaults for the template
+	/// file.
+	pub escape: Option<String>,
+	/// The bounds of the implementations of the template, they replace its
+	/// inferred `Display` bounds.
+	pub bound: Option<Vec<WherePredicate>>,
+	/// Where strict mode was enabled, it can not be disabled afterwards.
+	strict_origin: Option<&'static str>,
+	/// Deprecation warnings which have to be emitted with the generated code.
+	pub warnings: TokenStream,
+}
+
+impl TemplateAttributes {
+	/// Parses all template attributes, `info` contains the crate defaults.
+	pub fn parse(
+		attrs: &[Attribute],
+		info: TemplateInfo,
+	) -> Result<Self, String>
+	{
+		let strict_origin = info.strict.then_some("for the crate");
+		Self::parse_with(attrs, info, strict_origin)
+	}
+
+	fn parse_with(
+		attrs: &[Attribute],
+		info: TemplateInfo,
+		strict_origin: Option<&'static str>,
+	) -> Result<Self, String>
+	{
+		let mut res = Self {
+			name: None,
+			path: None,
+			info,
+			escape: None,
+			bound: None,
+			strict_origin,
+			warnings: TokenStream::new(),
+		};
+
+		for attr in attrs {
+			let name = match attr.path.get_ident() {
+				Some(name) => name.to_string(),
+				None => continue,
+			};
+			match name.as_str() {
+				TEMPLATE_ATTRIBUTE if !is_named(attr) => {
+					res.parse_template(attr)?
+				}
+				TEMPLATE_PATH_MACRO => {
+					match attr.parse_meta() {
+						Ok(Meta::NameValue(nv)) => {
+							res.path = Some(lit_str(&nv.lit, "path")?);
+						}
+						_ => {
+							return Err(format!(
+								"Expected #[{}=\"<path>\"]",
+								TEMPLATE_PATH_MACRO
+							))
+						}
+					}
+					res.deprecated(attr, "#[template(path = \"...\")]");
+				}
+				TEMPLATE_DEBUG_MACRO => {
+					res.info.debug_print = true;
+					res.deprecated(attr, "#[template(debug)]");
+				}
+				_ => {}
+			}
+		}
+
+		Ok(res)
+	}
+
+	/// Parses the attributes of an enum variant, the attributes of the enum
+	/// are the defaults.
+	pub fn inherit(&self, attrs: &[Attribute]) -> Result<Self, String> {
+		let mut res =
+			Self::parse_with(attrs, self.info.clone(), self.strict_origin)?;
+		if res.escape.is_none() {
+			res.escape = self.escape.clone();
+		}
+		Ok(res)
+	}
+
+	/// Parses all named templates, e.g. `#[template(name = "html", ...)]`.
+	///
+	/// The options of the unnamed template are the defaults for them.
+	pub fn named(&self, attrs: &[Attribute]) -> Result<Vec<Self>, String> {
+		attrs
+			.iter()
+			.filter(|attr| attr.path.is_ident(TEMPLATE_ATTRIBUTE))
+			.filter(|attr| is_named(attr))
+			.map(|attr| {
+				let mut res = Self {
+					name: None,
+					path: None,
+					info: self.info.clone(),
+					escape: self.escape.clone(),
+					bound: None,
+					strict_origin: self.strict_origin,
+					warnings: TokenStream::new(),
+				};
+				res.parse_template(attr)?;
+				Ok(res)
+			})
+			.collect()
+	}
+
+	fn parse_template(&mut self, attr: &Attribute) -> Result<(), String> {
+		for nested in template_list(attr)? {
+			let (name, value) = match nested {
+				NestedMeta::Meta(Meta::Path(path)) => (path, None),
+				NestedMeta::Meta(Meta::NameValue(nv)) => {
+					(nv.path, Some(nv.lit))
+				}
+				_ => {
+					return Err(format!(
+						"Expected `key` or `key = value` in #[{}(...)]",
+						TEMPLATE_ATTRIBUTE
+					))
+				}
+			};
+			let name =
+				name.get_ident().map(|i| i.to_string()).unwrap_or_default();
+			match (name.as_str(), value) {
+				("path", Some(lit)) => self.path = Some(lit_str(&lit, "path")?),
+				("name", Some(lit)) => {
+					let name = lit_str(&lit, "name")?.value();
+					if syn::parse_str::<syn::Ident>(&name).is_err() {
+						return Err(format!(
+							"The template name \"{}\" is no identifier",
+							name
+						));
+					}
+					self.name = Some(name)
+				}
+				("escape", Some(lit)) => {
+					self.escape = Some(lit_str(&lit, "escape")?.value())
+				}
+				("cleanws", lit) | ("clean_whitespace", lit) => {
+					self.info.clean_whitespace = lit_bool(lit.as_ref(), &name)?
+				}
+				("strict", lit) => {
+					let strict = lit_bool(lit.as_ref(), &name)?;
+					match self.strict_origin {
+						Some(origin) if !strict => {
+							return Err(format!(
+								"Strict mode is enabled {} and can not be \
+								 disabled for a single template",
+								origin
+							))
+						}
+						None if strict => {
+							self.strict_origin = Some("for the whole type")
+						}
+						_ => {}
+					}
+					self.info.strict = strict;
+				}
+				("restricted", lit) => {
+					self.info.policy.restricted = lit_bool(lit.as_ref(), &name)?
+				}
+				("allow", Some(lit)) => {
+					self.info.policy.restricted = true;
+					for construct in lit_str(&lit, "allow")?.value().split(',')
+					{
+						let construct = construct.trim();
+						self.info.policy.allowed.push(
+							Construct::from_name(construct).ok_or_else(
+								|| {
+									format!(
+										"Unknown construct \"{}\" in `allow`, \
+										 expected `if` or `for`",
+										construct
+									)
+								},
+							)?,
+						);
+					}
+				}
+				("debug", lit) => {
+					self.info.debug_print = lit_bool(lit.as_ref(), &name)?
+				}
+				("hot_reload", lit) => {
+					self.info.hot_reload = lit_bool(lit.as_ref(), &name)?
+				}
+				("markers", Some(lit)) => {
+					self.info.markers = Some(lit_str(&lit, "markers")?.value())
+				}
+				("bound", Some(lit)) => {
+					let bound = lit_str(&lit, "bound")?.value();
+					type Predicates = Punctuated<WherePredicate, Token![,]>;
+					let parser = Predicates::parse_terminated;
+					let predicates =
+						parser.parse_str(&bound).map_err(|err| {
+							format!("Invalid bound \"{}\": {}", bound, err)
+						})?;
+					self.bound.get_or_insert_with(Vec::new).extend(predicates);
+				}
+				(name, _) => {
+					return Err(format!(
+						"Unrecognized option \"{}\" in #[{}(...)]",
+						name, TEMPLATE_ATTRIBUTE
+					))
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Emits a deprecation warning pointing at the attribute.
+	///
+	/// Proc macros can not emit warnings on stable, so instead a use of a
+	/// deprecated item is generated with the span of the attribute.
+	fn deprecated(&mut self, attr: &Attribute, replacement: &str) {
+		let name = attr.path.get_ident().unwrap();
+		let note = format!(
+			"the #[{}] attribute is deprecated, use {} instead",
+			name, replacement
+		);
+		self.warnings.extend(quote_spanned! {name.span()=>
+			const _: () = {
+				#[deprecated(note = #note)]
+				#[allow(non_upper_case_globals)]
+				const #name: () = ();
+				#name
+			};
+		});
+	}
+}
+
+fn template_list(
+	attr: &Attribute,
+) -> Result<Punctuated<NestedMeta, Token![,]>, String>
+{
+	match attr.parse_meta() {
+		Ok(Meta::List(list)) => Ok(list.nested),
+		_ => Err(format!("Expected #[{}(...)]", TEMPLATE_ATTRIBUTE)),
+	}
+}
+
+/// Checks if the attribute declares a named template.
+fn is_named(attr: &Attribute) -> bool {
+	template_list(attr).is_ok_and(|list| {
+		list.iter().any(|nested| match nested {
+			NestedMeta::Meta(Meta::NameValue(nv)) => nv.path.is_ident("name"),
+			_ => false,
+		})
+	})
+}
+
+fn lit_str(lit: &Lit, name: &str) -> Result<LitStr, String> {
+	match lit {
+		Lit::Str(s) => Ok(s.clone()),
+		_ => Err(format!(
+			"Expected a string for the template option \"{}\"",
+			name
+		)),
+	}
+}
+
+/// A flag can be given as `flag` or `flag = true`/`flag = false`.
+fn lit_bool(lit: Option<&Lit>, name: &str) -> Result<bool, String> {
+	match lit {
+		None => Ok(true),
+		Some(Lit::Bool(b)) => Ok(b.value),
+		Some(Lit::Str(s)) if s.value().parse::<bool>().is_ok() => {
+			Ok(s.value().parse().unwrap())
+		}
+		_ => {
+			Err(format!("Expected a bool for the template option \"{}\"", name))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(
+		attrs: &str,
+		info: TemplateInfo,
+	) -> Result<(TemplateAttributes, Vec<TemplateAttributes>), String>
+	{
+		let item: syn::DeriveInput =
+			syn::parse_str(&format!("{} struct A;", attrs)).unwrap();
+		let attributes = TemplateAttributes::parse(&item.attrs, info)?;
+		let named = attributes.named(&item.attrs)?;
+		Ok((attributes, named))
+	}
+
+	#[test]
+	fn options() {
+		let (attributes, named) = parse(
+			"#[template(path = \"a.tt\", hot_reload, markers = \"{line}\", \
+			 bound = \"T: Copy\")] #[template(name = \"b\", path = \"b.tt\", \
+			 debug)]",
+			TemplateInfo::default(),
+		)
+		.unwrap();
+		assert_eq!(attributes.path.unwrap().value(), "a.tt");
+		assert!(attributes.info.hot_reload);
+		assert_eq!(attributes.info.markers.as_deref(), Some("{line}"));
+		assert_eq!(attributes.bound.unwrap().len(), 1);
+		assert!(!attributes.info.debug_print);
+
+		assert_eq!(named.len(), 1);
+		assert_eq!(named[0].name.as_deref(), Some("b"));
+		assert!(named[0].info.hot_reload && named[0].info.debug_print);
+	}
+
+	#[test]
+	fn errors() {
+		let error = |attrs, info| parse(attrs, info).err().unwrap();
+		assert_eq!(
+			error("#[template(cleanws = 1)]", TemplateInfo::default()),
+			"Expected a bool for the template option \"cleanws\""
+		);
+		assert_eq!(
+			error("#[template(path)]", TemplateInfo::default()),
+			"Unrecognized option \"path\" in #[template(...)]"
+		);
+		let strict = TemplateInfo { strict: true, ..TemplateInfo::default() };
+		assert_eq!(
+			error("#[template(strict = false)]", strict),
+			"Strict mode is enabled for the crate and can not be disabled \
+			 for a single template"
+		);
+	}
+}
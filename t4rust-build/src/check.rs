@@ -0,0 +1,198 @@
+//! Checks templates without generating their code, for editors and command
+//! line tools.
+//!
+//! ```
+//! use t4rust_build::TemplateInfo;
+//!
+//! let problems =
+//!     t4rust_build::check::check(&TemplateInfo::default(), "<# let x = ; #>");
+//! assert_eq!((problems[0].line, problems[0].column), (1, 12));
+//! assert_eq!(problems[0].message, "expected expression");
+//! ```
+
+use syn::parse::Parser;
+use t4rust_parser::{directives, NodeKind, Template, BLOCK_END};
+
+use crate::TemplateInfo;
+
+/// A problem in a template.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+	/// The 1-based line.
+	pub line: usize,
+	/// The 1-based column in characters.
+	pub column: usize,
+	pub message: String,
+}
+
+/// Checks a template, `initial` are the settings it is compiled with.
+///
+/// The checks run in stages, only the problems of the first failing stage
+/// are returned:
+/// 1. The template syntax
+/// 2. Unknown directives and parameters and invalid parameter values
+/// 3. The checks of the compiler, e.g. unbalanced brackets and strict
+///    escaping
+/// 4. The code and expression blocks have to form valid Rust
+pub fn check(initial: &TemplateInfo, source: &str) -> Vec<Problem> {
+	let at = |index, message| {
+		let (line, column) = t4rust_parser::line_col(source, index);
+		Problem { line, column, message }
+	};
+
+	let template = match t4rust_parser::parse(source) {
+		Ok(template) => template,
+		Err(err) => return vec![at(err.index, err.reason)],
+	};
+
+	let problems: Vec<_> = directives::validate(&template)
+		.into_iter()
+		.map(|problem| at(problem.span.start, problem.value))
+		.collect();
+	if !problems.is_empty() {
+		return problems;
+	}
+
+	if let Err(err) = crate::parse_all(&mut initial.clone(), source) {
+		return vec![at(err.index, err.reason)];
+	}
+
+	match syn::Block::parse_within.parse_str(&skeleton(&template)) {
+		Ok(_) => Vec::new(),
+		// Errors at the end of the input have no position
+		Err(err) if err.to_string().starts_with("unexpected end of input") => {
+			vec![at(source.len(), err.to_string())]
+		}
+		Err(err) => {
+			let start = err.span().start();
+			vec![Problem {
+				line: start.line,
+				column: start.column + 1,
+				message: err.to_string(),
+			}]
+		}
+	}
+}
+
+/// Replaces everything except the code with whitespace, so that positions in
+/// the returned Rust code are the same as in the template.
+///
+/// Like in the generated code, an expression becomes the statement `(expr);`
+/// and text becomes the statement `{}`.
+fn skeleton(template: &Template) -> String {
+	let source = template.source;
+	let blank = |s: &str| -> String {
+		s.chars().map(|c| if c == '\n' { '\n' } else { ' ' }).collect()
+	};
+
+	let mut res = String::with_capacity(source.len());
+	let mut after_code = false;
+	for node in &template.nodes {
+		let raw = &source[node.span.clone()];
+		match &node.kind {
+			NodeKind::Text { .. } => {
+				let text = blank(raw);
+				let line = text.find(|c| c != '\n');
+				match line.map(|start| (start, &text[start..])) {
+					Some((start, rest)) => {
+						let len = rest.find('\n').unwrap_or(rest.len());
+						if len >= 2 {
+							res.push_str(&text[..start]);
+							res.push_str("{}");
+							res.push_str(&rest[2..]);
+						} else if after_code {
+							// The end of the code block is blank
+							res.truncate(res.len() - BLOCK_END.len());
+							res.push_str("{}");
+							res.push_str(&text);
+						} else {
+							// After an expression or directive, there is no
+							// statement which could miss a semicolon
+							res.push_str(&text[..start]);
+							res.push(';');
+							res.push_str(&rest[1..]);
+						}
+					}
+					// Only line breaks, which print nothing with cleanws
+					None => res.push_str(&text),
+				}
+			}
+			NodeKind::Code(block) | NodeKind::Expr(block) => {
+				let content = &source[block.content.clone()];
+				let open = block.content.start - node.span.start;
+				let is_expr = matches!(node.kind, NodeKind::Expr(_));
+				res.push_str(&" ".repeat(open - 1));
+				res.push(if is_expr { '(' } else { ' ' });
+				// Keep the length of escaped block ends
+				let escaped = [BLOCK_END, BLOCK_END].concat();
+				res.push_str(&content.replace(&escaped, "#>  "));
+				res.push_str(if is_expr { ");" } else { "  " });
+			}
+			NodeKind::Directive(_) => res.push_str(&blank(raw)),
+		}
+		after_code = matches!(node.kind, NodeKind::Code(_));
+	}
+	res
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn check(source: &str) -> Vec<(usize, usize, String)> {
+		super::check(&TemplateInfo::default(), source)
+			.into_iter()
+			.map(|p| (p.line, p.column, p.message))
+			.collect()
+	}
+
+	#[test]
+	fn skeletons() {
+		let template =
+			t4rust_parser::parse("ä<# if a { #>\n<#= b #>x\n<# } #>").unwrap();
+		assert_eq!(skeleton(&template), ";   if a {   \n  ( b );;\n   }   ");
+	}
+
+	#[test]
+	fn stages() {
+		assert_eq!(
+			check("a\n<#= b"),
+			[(2, 1, "Unclosed code or expression block".into())]
+		);
+		assert_eq!(
+			check("<#@ template cleanws=\"yes\" debug=\"no\" #>"),
+			[
+				(1, 23, "Expected `true` or `false` for `cleanws`".into()),
+				(1, 35, "Expected `true` or `false` for `debug`".into()),
+			]
+		);
+		assert_eq!(
+			check("<# if a { #>\n<# ) #>"),
+			[(
+				2,
+				4,
+				"Found ')' but the '{' opened on line 1 is still unclosed"
+					.into()
+			)]
+		);
+		assert_eq!(
+			check("<# let a = 1; #>\n  <# let b = ; #>\n"),
+			[(2, 14, "expected expression".into())]
+		);
+		// A short text line after a code block keeps its line break
+		assert_eq!(
+			check("<# let a = 1; #>\nx\n<# let b = ; #>"),
+			[(3, 12, "expected expression".into())]
+		);
+		assert_eq!(
+			check("<# let a = #>\n"),
+			[(2, 1, "unexpected end of input, expected expression".into())]
+		);
+		// Text is a statement of its own
+		assert_eq!(
+			check("<# a() #>x<# ; #>"),
+			[(1, 8, "unexpected token".into())]
+		);
+		assert_eq!(check("<# for i in 0..3 { #>\n<#= i #>, \n<# } #>"), []);
+	}
+}
@@ -0,0 +1,251 @@
+//! Reads crate-wide template defaults from `[package.metadata.t4rust]` in the
+//! manifest of the crate that uses the templates.
+//!
+//! ```toml
+//! [package.metadata.t4rust]
+//! root = "templates"
+//! cleanws = true
+//! strict = true
+//! escape = "crate::esc::html"
+//!
+//! [package.metadata.t4rust.extensions]
+//! txt = ""
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use toml::Value;
+
+use crate::escape;
+use crate::{Error, TemplateInfo};
+
+const METADATA_TABLE: &str = "t4rust";
+
+#[derive(Default)]
+pub struct CrateConfig {
+	/// Template paths are relative to this directory.
+	pub root: PathBuf,
+	pub clean_whitespace: Option<bool>,
+	pub strict: bool,
+	/// Reload the texts of templates in debug builds.
+	pub hot_reload: bool,
+	/// The source markers around the output of each part.
+	pub markers: Option<String>,
+	/// Record which blocks are rendered in builds with the `t4rust_coverage`
+	/// cfg, the generated code uses `t4rust-coverage`.
+	pub coverage: bool,
+	/// Report render errors with the template line, the generated code uses
+	/// `t4rust-error`.
+	pub errors: bool,
+	/// The default escape function.
+	pub escape: Option<String>,
+	/// Escape functions by the inner extension of the template file, e.g.
+	/// `html` for `index.html.tt`.
+	pub extensions: HashMap<String, String>,
+}
+
+impl CrateConfig {
+	/// Loads the configuration from the `Cargo.toml` in `manifest_dir`.
+	///
+	/// A missing manifest or metadata table results in the default
+	/// configuration, paths are then relative to the manifest directory.
+	pub fn load(manifest_dir: &Path) -> Result<Self, Error> {
+		let mut config =
+			Self { root: manifest_dir.to_path_buf(), ..Self::default() };
+
+		let manifest_path = manifest_dir.join("Cargo.toml");
+		let manifest = match std::fs::read_to_string(&manifest_path) {
+			Ok(manifest) => manifest,
+			Err(_) => return Ok(config),
+		};
+		let error = |reason| Error::Config(manifest_path.clone(), reason);
+		let manifest: Value = manifest
+			.parse()
+			.map_err(|err| error(format!("Could not parse: {}", err)))?;
+		let table = match manifest
+			.get("package")
+			.and_then(|p| p.get("metadata"))
+			.and_then(|m| m.get(METADATA_TABLE))
+		{
+			Some(table) => table,
+			None => return Ok(config),
+		};
+
+		let table = table.as_table().ok_or_else(|| {
+			error(format!(
+				"Expected [package.metadata.{}] to be a table",
+				METADATA_TABLE
+			))
+		})?;
+		for (key, value) in table {
+			match key.as_str() {
+				"root" => {
+					config.root.push(expect_str(key, value).map_err(error)?)
+				}
+				"cleanws" | "clean_whitespace" => {
+					config.clean_whitespace =
+						Some(expect_bool(key, value).map_err(error)?)
+				}
+				"strict" => {
+					config.strict = expect_bool(key, value).map_err(error)?
+				}
+				"hot_reload" => {
+					config.hot_reload =
+						expect_bool(key, value).map_err(error)?
+				}
+				"coverage" => {
+					config.coverage = expect_bool(key, value).map_err(error)?
+				}
+				"errors" => {
+					config.errors = expect_bool(key, value).map_err(error)?
+				}
+				"markers" => {
+					let markers = expect_str(key, value).map_err(error)?;
+					config.markers = Some(markers.into())
+				}
+				"escape" => {
+					let escape = expect_str(key, value).map_err(error)?;
+					config.escape = Some(escape.into())
+				}
+				"extensions" => {
+					let extensions = value.as_table().ok_or_else(|| {
+						error(format!(
+							"Expected a table for the t4rust option \"{}\"",
+							key
+						))
+					})?;
+					for (ext, escape) in extensions {
+						let escape = expect_str(ext, escape).map_err(error)?;
+						config.extensions.insert(ext.clone(), escape.into());
+					}
+				}
+				_ => {
+					return Err(error(format!(
+						"Unrecognized option \"{}\" in [package.metadata.{}]",
+						key, METADATA_TABLE
+					)));
+				}
+			}
+		}
+
+		Ok(config)
+	}
+
+	/// The initial settings for all templates of the crate.
+	pub fn info(&self) -> TemplateInfo {
+		let mut info = TemplateInfo::default();
+		if let Some(clean_whitespace) = self.clean_whitespace {
+			info.clean_whitespace = clean_whitespace;
+		}
+		info.strict = self.strict;
+		info.hot_reload = self.hot_reload;
+		info.markers = self.markers.clone();
+		info.coverage = self.coverage;
+		info.errors = self.errors;
+		info
+	}
+
+	/// Picks the default escape function for a template file.
+	///
+	/// A mapping for the inner extension of the file takes precedence over the
+	/// crate-wide `escape` option. Without any configuration the built-in
+	/// escaper for the extension is used.
+	pub fn escape_for(&self, path: &Path) -> Option<&str> {
+		let ext = inner_extension(path);
+		ext.and_then(|ext| self.extensions.get(ext))
+			.or(self.escape.as_ref())
+			.map(String::as_str)
+			.or_else(|| ext.and_then(escape::for_extension))
+	}
+}
+
+/// Returns `html` for `index.html.tt`.
+pub fn inner_extension(path: &Path) -> Option<&str> {
+	Path::new(path.file_stem()?).extension()?.to_str()
+}
+
+fn expect_str<'a>(key: &str, value: &'a Value) -> Result<&'a str, String> {
+	value.as_str().ok_or_else(|| {
+		format!("Expected a string for the t4rust option \"{}\"", key)
+	})
+}
+
+fn expect_bool(key: &str, value: &Value) -> Result<bool, String> {
+	value.as_bool().ok_or_else(|| {
+		format!("Expected a bool for the t4rust option \"{}\"", key)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn load(name: &str, manifest: &str) -> Result<CrateConfig, Error> {
+		let dir = std::env::temp_dir()
+			.join(format!("t4rust-{}-{}", name, std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(dir.join("Cargo.toml"), manifest).unwrap();
+		let config = CrateConfig::load(&dir);
+		std::fs::remove_dir_all(&dir).unwrap();
+		config
+	}
+
+	#[test]
+	fn defaults_without_metadata() {
+		let config = load("empty", "[package]\nname = \"x\"\n").unwrap();
+		assert_eq!(config.clean_whitespace, None);
+		assert_eq!(config.escape_for(Path::new("a.tt")), None);
+		assert_eq!(config.escape_for(Path::new("a.txt.tt")), None);
+		assert_eq!(config.escape_for(Path::new("a.html.tt")), Some("html"));
+		assert_eq!(config.escape_for(Path::new("a.svg.tt")), Some("xml"));
+	}
+
+	#[test]
+	fn metadata() {
+		let config = load(
+			"metadata",
+			r#"
+			[package]
+			name = "x"
+
+			[package.metadata.t4rust]
+			root = "templates"
+			cleanws = true
+			strict = true
+			coverage = true
+			errors = true
+			escape = "crate::esc::html"
+
+			[package.metadata.t4rust.extensions]
+			txt = ""
+			"#,
+		)
+		.unwrap();
+		assert!(config.root.ends_with("templates"));
+		assert!(config.info().clean_whitespace);
+		assert!(config.info().strict);
+		assert!(config.info().coverage);
+		assert!(config.info().errors);
+		assert_eq!(
+			config.escape_for(Path::new("page.html.tt")),
+			Some("crate::esc::html")
+		);
+		assert_eq!(config.escape_for(Path::new("mail.txt.tt")), Some(""));
+	}
+
+	#[test]
+	fn invalid_metadata() {
+		let manifest = "[package.metadata.t4rust]\nstrict = \"yes\"\n";
+		let err = load("invalid", manifest).err().unwrap();
+		assert!(err.to_string().ends_with(
+			"Cargo.toml: Expected a bool for the t4rust option \"strict\""
+		));
+		let err = load("unknown", "[package.metadata.t4rust]\nroots = \"\"\n")
+			.err()
+			.unwrap();
+		assert!(err.to_string().ends_with(
+			"Unrecognized option \"roots\" in [package.metadata.t4rust]"
+		));
+	}
+}
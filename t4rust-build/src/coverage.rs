@@ -0,0 +1,60 @@
+//! Coverage instrumentation, the generated code records the rendered text and
+//! code blocks in the registry of the `t4rust-coverage` crate.
+//!
+//! All statements are only compiled with `--cfg t4rust_coverage`, so that
+//! other builds do not pay for the instrumentation.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// The name of the binding with the template file.
+const FILE: &str = "__t4rust_coverage_file";
+
+/// Binds the template file for the [`hit`] and [`register`] statements.
+pub(crate) fn prelude(path: &str) -> TokenStream {
+	let file = proc_macro2::Ident::new(FILE, proc_macro2::Span::call_site());
+	quote! {
+		#[cfg(t4rust_coverage)]
+		let #file: &'static str = #path;
+	}
+}
+
+/// The last line of a block which starts on `line`, a trailing newline does
+/// not belong to another line.
+pub(crate) fn end_line(line: usize, content: &str) -> usize {
+	let content = content.strip_suffix('\n').unwrap_or(content);
+	line + content.matches('\n').count()
+}
+
+/// Counts a render of the block which starts on `line`.
+pub(crate) fn hit(line: usize) -> String {
+	format!(
+		"#[cfg(t4rust_coverage)] ::t4rust_coverage::hit({}, {});\n",
+		FILE, line
+	)
+}
+
+/// Adds the `(start, end)` lines of all instrumented blocks to the registry
+/// once.
+pub(crate) fn register(blocks: &[(usize, usize)]) -> String {
+	let blocks: Vec<_> =
+		blocks.iter().map(|(s, e)| format!("({}, {})", s, e)).collect();
+	format!(
+		"#[cfg(t4rust_coverage)] {{ \
+		 static REGISTERED: ::std::sync::Once = ::std::sync::Once::new(); \
+		 REGISTERED.call_once(|| ::t4rust_coverage::register({}, &[{}])); }}\n",
+		FILE,
+		blocks.join(", ")
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	#[test]
+	fn end_line() {
+		assert_eq!(super::end_line(3, "a"), 3);
+		assert_eq!(super::end_line(3, "a\n"), 3);
+		assert_eq!(super::end_line(3, "a\n\n"), 4);
+		assert_eq!(super::end_line(3, "\n a\nb"), 5);
+	}
+}
@@ -0,0 +1,219 @@
+//! Debug artifacts of templates which are compiled with the `debug` option.
+//!
+//! They are written to `<target dir>/t4rust/<crate>/<key>/`, where the key
+//! tells the structs of a crate apart, see [`item_key`] and [`template_key`]:
+//! - `<template path>.json` contains the parts of each template of the struct
+//!   after parsing, after `parse_postprocess` and after `parse_optimize`
+//! - `code.rs` contains the pretty-printed generated code
+
+use std::path::{Path, PathBuf};
+
+use proc_macro2::TokenStream;
+use serde_json::{json, Value};
+
+use crate::{Part, TemplatePart};
+
+/// The parts of a template after each compilation step.
+#[derive(Debug, Clone)]
+pub struct Stages {
+	/// The template file.
+	pub path: PathBuf,
+	pub parsed: Value,
+	pub postprocessed: Value,
+	pub optimized: Value,
+}
+
+/// The artifact directory of the struct with the `key` in the crate which is
+/// currently compiled.
+///
+/// The target directory is taken from `CARGO_TARGET_DIR` or is the `target`
+/// directory of the workspace which contains `manifest_dir`.
+pub fn directory(manifest_dir: &Path, key: &Path) -> PathBuf {
+	let target = std::env::var_os("CARGO_TARGET_DIR").map_or_else(
+		|| workspace_root(manifest_dir).join("target"),
+		PathBuf::from,
+	);
+	let krate = std::env::var("CARGO_CRATE_NAME")
+		.or_else(|_| std::env::var("CARGO_PKG_NAME"))
+		.unwrap_or_else(|_| "unknown".into());
+	target.join("t4rust").join(krate).join(key)
+}
+
+/// The root of the workspace which contains the crate in `manifest_dir`.
+///
+/// Like cargo, this is the closest directory with a `[workspace]` manifest or
+/// the directory which `package.workspace` points to. Crates outside of a
+/// workspace are their own root.
+pub fn workspace_root(manifest_dir: &Path) -> PathBuf {
+	let manifest = |dir: &Path| -> Option<toml::Value> {
+		std::fs::read_to_string(dir.join("Cargo.toml")).ok()?.parse().ok()
+	};
+	if let Some(workspace) = manifest(manifest_dir)
+		.as_ref()
+		.and_then(|m| m.get("package")?.get("workspace")?.as_str())
+	{
+		return manifest_dir.join(workspace);
+	}
+	manifest_dir
+		.ancestors()
+		.find(|dir| manifest(dir).is_some_and(|m| m.get("workspace").is_some()))
+		.unwrap_or(manifest_dir)
+		.to_path_buf()
+}
+
+/// The key of a derived struct, the module path of its source file below
+/// `root` and its name, e.g. `pages/Page` for `Page` in `src/pages.rs`.
+///
+/// Structs with the same name in modules of different files get different
+/// keys. Inline modules are not part of the key, so structs of the same name
+/// in the inline modules of one file share their artifacts.
+pub fn item_key(root: &Path, file: &Path, name: &str) -> PathBuf {
+	module_path(&relative(root, file)).join(name)
+}
+
+/// The module path of a source file as path, e.g. `pages/list` for
+/// `src/pages/list.rs` or `src/pages/list/mod.rs`.
+///
+/// The files directly in `tests`, `examples`, `benches` and `src/bin` are the
+/// roots of their own crates, the crate name already tells them apart.
+fn module_path(file: &Path) -> PathBuf {
+	let mut names: Vec<_> = file
+		.with_extension("")
+		.iter()
+		.map(|name| name.to_string_lossy().into_owned())
+		.collect();
+	let crate_dirs = match names.first().map(String::as_str) {
+		Some("src") if names.len() > 2 && names[1] == "bin" => 3,
+		Some("src") => 1,
+		Some("tests" | "examples" | "benches") => 2,
+		_ => 0,
+	};
+	if matches!(names.last().map(String::as_str), Some("lib" | "main" | "mod"))
+	{
+		names.pop();
+	}
+	names.drain(..crate_dirs.min(names.len()));
+	names.iter().collect()
+}
+
+/// The key of a struct generated for a template file, the template path
+/// relative to `root` and the struct name, e.g. `templates/page.tt/Page`.
+pub fn template_key(root: &Path, template: &Path, name: &str) -> PathBuf {
+	relative(root, template).join(name)
+}
+
+/// `path` relative to `root`, or only the file name if it is not below
+/// `root`.
+fn relative(root: &Path, path: &Path) -> PathBuf {
+	let canonical = |path: &Path| {
+		path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+	};
+	match canonical(path).strip_prefix(canonical(root)) {
+		Ok(relative) => relative.to_path_buf(),
+		Err(_) => PathBuf::from(path.file_name().unwrap_or_default()),
+	}
+}
+
+/// Writes the stages of a template to `dir` and returns the path of the file.
+///
+/// The file is named after the template path relative to `root`, so that all
+/// templates of a struct can be told apart.
+pub fn write_parts(
+	dir: &Path,
+	root: &Path,
+	stages: &Stages,
+) -> std::io::Result<PathBuf>
+{
+	let mut file_name = relative(root, &stages.path).into_os_string();
+	file_name.push(".json");
+	let path = dir.join(file_name);
+
+	let value = json!({
+		"template": stages.path,
+		"parsed": stages.parsed,
+		"postprocessed": stages.postprocessed,
+		"optimized": stages.optimized,
+	});
+	let content = serde_json::to_string_pretty(&value).expect("Invalid JSON");
+	std::fs::create_dir_all(path.parent().unwrap())?;
+	std::fs::write(&path, content)?;
+	Ok(path)
+}
+
+/// Writes the pretty-printed `code` to `dir` and returns the path of the
+/// file.
+pub fn write_code(dir: &Path, code: &TokenStream) -> std::io::Result<PathBuf> {
+	let path = dir.join("code.rs");
+	let code = match syn::parse2(code.clone()) {
+		Ok(file) => prettyplease::unparse(&file),
+		// Keep the unformatted code, the compiler reports the error
+		Err(_) => code.to_string(),
+	};
+	std::fs::create_dir_all(dir)?;
+	std::fs::write(&path, code)?;
+	Ok(path)
+}
+
+/// The JSON representation of template parts.
+pub(crate) fn parts(parts: &[Part]) -> Value {
+	let parts = parts.iter().map(|part| {
+		let (kind, value) = match &part.kind {
+			TemplatePart::Text(value) => ("text", json!(value)),
+			TemplatePart::Code(value) => ("code", json!(value)),
+			TemplatePart::Expr(value) => ("expr", json!(value)),
+			TemplatePart::Directive(dir) => (
+				"directive",
+				json!({
+					"name": dir.name,
+					"params": dir.params,
+				}),
+			),
+		};
+		json!({ "kind": kind, "line": part.line, "value": value })
+	});
+	Value::Array(parts.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::Path;
+
+	use crate::TemplateInfo;
+
+	#[test]
+	fn workspace_root() {
+		let member = Path::new(env!("CARGO_MANIFEST_DIR"));
+		let root = member.parent().unwrap();
+		assert_eq!(super::workspace_root(member), root);
+		assert_eq!(super::workspace_root(root), root);
+	}
+
+	#[test]
+	fn module_path() {
+		let path = |file| super::module_path(Path::new(file));
+		assert_eq!(path("src/lib.rs"), Path::new(""));
+		assert_eq!(path("src/main.rs"), Path::new(""));
+		assert_eq!(path("src/pages.rs"), Path::new("pages"));
+		assert_eq!(path("src/pages/mod.rs"), Path::new("pages"));
+		assert_eq!(path("src/pages/list.rs"), Path::new("pages/list"));
+		assert_eq!(path("src/bin.rs"), Path::new("bin"));
+		assert_eq!(path("src/bin/tool.rs"), Path::new(""));
+		assert_eq!(path("src/bin/tool/main.rs"), Path::new(""));
+		assert_eq!(path("src/bin/tool/args.rs"), Path::new("args"));
+		assert_eq!(path("tests/render.rs"), Path::new(""));
+		assert_eq!(path("examples/site/pages.rs"), Path::new("pages"));
+	}
+
+	#[test]
+	fn parts_json() {
+		let parts = crate::parse_all(
+			&mut TemplateInfo::default(),
+			"a\n<#@ escape function=\"html\" #><#= b #>",
+		)
+		.unwrap();
+		assert_eq!(
+			super::parts(&parts).to_string(),
+			r#"[{"kind":"text","line":1,"value":"a\n"},{"kind":"directive","line":2,"value":{"name":"escape","params":[["function","html"]]}},{"kind":"text","line":2,"value":""},{"kind":"expr","line":2,"value":" b "}]"#
+		);
+	}
+}
@@ -0,0 +1,42 @@
+//! Render errors, the generated code remembers the line of the block which is
+//! rendered and reports failures to the `t4rust-error` crate.
+
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+
+/// The name of the binding with the line of the current block.
+const LINE: &str = "__t4rust_at";
+
+/// Sets the current line to `line`.
+pub(crate) fn at(line: usize) -> String { format!("{} = {};\n", LINE, line) }
+
+/// Runs the printing statements in a closure and turns their failure into a
+/// render error at the current line.
+///
+/// The closure also defines `bail!` for the template code.
+pub(crate) fn wrap(path: Option<&str>, tokens: TokenStream) -> TokenStream {
+	let path = match path {
+		Some(path) => quote! { ::std::option::Option::Some(#path) },
+		None => quote! { ::std::option::Option::None },
+	};
+	let line = Ident::new(LINE, Span::call_site());
+	quote! {
+		let mut #line: usize = 0;
+		#[allow(unused_assignments, clippy::redundant_closure_call)]
+		let __t4rust_result = (|| -> ::std::fmt::Result {
+			#[allow(unused_macros)]
+			macro_rules! bail {
+				($($arg:tt)*) => {
+					return ::std::result::Result::Err(
+						::t4rust_error::raise(::std::format!($($arg)*))
+					)
+				};
+			}
+			#tokens
+			::std::result::Result::Ok(())
+		})();
+		if __t4rust_result.is_err() {
+			return ::std::result::Result::Err(::t4rust_error::fail(#path, #line));
+		}
+	}
+}
@@ -0,0 +1,101 @@
+//! Escape functions which are generated into the template code and can be
+//! selected by name instead of a function path.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::lexer::{self, Token};
+
+/// Marks an expression which is printed without escaping, e.g.
+/// `<#= raw!(self.html) #>`.
+pub(crate) const RAW_MARKER: &str = "raw";
+
+/// Disables escaping, same as an empty function name.
+pub(crate) const NO_ESCAPE: &str = "none";
+
+/// Maps the name of a built-in escaper to the function generated for it.
+pub(crate) fn builtin_function(name: &str) -> Option<&'static str> {
+	match name {
+		"html" => Some("__t4rust_escape_html"),
+		"xml" => Some("__t4rust_escape_xml"),
+		NO_ESCAPE => Some(""),
+		_ => None,
+	}
+}
+
+/// The built-in escaper for the inner extension of a template file, e.g.
+/// `html` for `index.html.tt`.
+pub(crate) fn for_extension(ext: &str) -> Option<&'static str> {
+	match ext {
+		"html" | "htm" | "xhtml" => Some("html"),
+		"xml" | "svg" | "rss" | "atom" => Some("xml"),
+		_ => None,
+	}
+}
+
+/// Returns the inner expression if the whole expression is wrapped in the
+/// raw marker.
+pub(crate) fn strip_raw_marker(expr: &str) -> Option<&str> {
+	let tokens = lexer::tokenize(expr);
+	match tokens.as_slice() {
+		[
+			(_, Token::Ident(RAW_MARKER)),
+			(_, Token::Punct('!')),
+			(open, Token::Punct('(')),
+			inner @ ..,
+			(close, Token::Punct(')')),
+		] => {
+			// `raw!(a) + raw!(b)` must not be taken apart
+			let mut depth = 0;
+			for (_, token) in inner {
+				match token {
+					Token::Punct('(') => depth += 1,
+					Token::Punct(')') if depth == 0 => return None,
+					Token::Punct(')') => depth -= 1,
+					_ => {}
+				}
+			}
+			Some(&expr[open + 1..*close])
+		}
+		_ => None,
+	}
+}
+
+/// Generates the definition of a built-in escaper.
+pub(crate) fn builtin_definition(name: &str) -> Option<TokenStream> {
+	match name {
+		"html" => Some(quote! {
+			fn __t4rust_escape_html(s: &str) -> ::std::string::String {
+				let mut res = ::std::string::String::with_capacity(s.len());
+				for c in s.chars() {
+					match c {
+						'&' => res.push_str("&amp;"),
+						'<' => res.push_str("&lt;"),
+						'>' => res.push_str("&gt;"),
+						'"' => res.push_str("&quot;"),
+						'\'' => res.push_str("&#x27;"),
+						c => res.push(c),
+					}
+				}
+				res
+			}
+		}),
+		"xml" => Some(quote! {
+			fn __t4rust_escape_xml(s: &str) -> ::std::string::String {
+				let mut res = ::std::string::String::with_capacity(s.len());
+				for c in s.chars() {
+					match c {
+						'&' => res.push_str("&amp;"),
+						'<' => res.push_str("&lt;"),
+						'>' => res.push_str("&gt;"),
+						'"' => res.push_str("&quot;"),
+						'\'' => res.push_str("&apos;"),
+						c => res.push(c),
+					}
+				}
+				res
+			}
+		}),
+		_ => None,
+	}
+}
@@ -0,0 +1,150 @@
+//! Hot reloading of template text in debug builds.
+//!
+//! The generated code of a hot reloaded template keeps its blocks, but reads
+//! the texts between them from the template file on every render. If the
+//! blocks in the file no longer have the structure hash of the compiled
+//! template, the compiled texts are used.
+
+// Most runtime functions are only called by the generated code
+#![allow(dead_code)]
+
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::{Part, TemplateInfo, TemplatePart};
+
+include!("hot_reload/runtime.rs");
+
+const RUNTIME: &str = include_str!("hot_reload/runtime.rs");
+
+/// The name of the binding with the reloaded texts.
+pub(crate) const TEXTS: &str = "__t4rust_texts";
+
+/// Generates the runtime functions and reloads the texts into [`TEXTS`].
+///
+/// `parts` have to be postprocessed, but not optimized, and every block has to
+/// be followed by a text.
+pub(crate) fn prelude(
+	initial: &TemplateInfo,
+	path: &str,
+	source: &str,
+	parts: &[Part],
+) -> TokenStream
+{
+	let (_, blocks) =
+		__t4rust_split(source).expect("Unclosed block in a template");
+	let hash = __t4rust_structure_hash(&blocks);
+	let trim = trimmed_blocks(initial, parts);
+
+	let runtime: TokenStream = RUNTIME.parse().expect("Invalid runtime code");
+	let texts = proc_macro2::Ident::new(TEXTS, proc_macro2::Span::call_site());
+	quote! {
+		#runtime
+		let #texts = __t4rust_hot_reload(#path, #hash, &[#(#trim),*]);
+	}
+}
+
+/// Tells for every block if `parse_postprocess` cleans the whitespace around
+/// it.
+fn trimmed_blocks(initial: &TemplateInfo, parts: &[Part]) -> Vec<bool> {
+	let mut info = initial.clone();
+	let mut trim = Vec::new();
+	for part in parts {
+		if let TemplatePart::Directive(dir) = &part.kind {
+			// Directives are validated by `parse_all`
+			let _ = crate::apply_directive(&mut info, dir);
+		}
+		if !part.kind.is_text() {
+			trim.push(
+				info.clean_whitespace && part.kind.should_trim_whitespace(),
+			);
+		}
+	}
+	trim
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn texts(source: &str, trim: &[bool]) -> Vec<String> {
+		let (mut texts, _) = __t4rust_split(source).unwrap();
+		__t4rust_clean(&mut texts, trim);
+		texts
+	}
+
+	#[test]
+	fn split() {
+		let (texts, blocks) =
+			__t4rust_split("a<#<#b<#= c #>#>#> #>\n<##>").unwrap();
+		assert_eq!(texts, ["a<#b", " #>\n", ""]);
+		assert_eq!(blocks, ["<#= c #>#>#>", "<##>"]);
+		assert!(__t4rust_split("a<# b").is_none());
+	}
+
+	#[test]
+	fn clean_like_postprocess() {
+		let sources = [
+			"<# a #>\n  x\n  <# b #>  \ny <#= c #>\n<# d #>",
+			"a\n<#@ template cleanws=\"true\" #>\n\t<# b #>\r\n<# c #>\n",
+			"<#@ template cleanws=\"false\" #>\n<# a #>\n<#= b #>\n",
+			"x<# a #>\n\n<# b #>\n  \n<# c #>",
+		];
+		for source in &sources {
+			let initial =
+				TemplateInfo { clean_whitespace: true, ..Default::default() };
+			let mut parts =
+				crate::parse_all(&mut initial.clone(), source).unwrap();
+			if !parts.last().unwrap().kind.is_text() {
+				let kind = TemplatePart::Text(String::new());
+				parts.push(Part { kind, line: 1 });
+			}
+			crate::parse_postprocess(&initial, &mut parts);
+			let compiled: Vec<_> = parts
+				.iter()
+				.filter_map(|part| match &part.kind {
+					TemplatePart::Text(text) => Some(text.as_str()),
+					_ => None,
+				})
+				.collect();
+			let trim = trimmed_blocks(&initial, &parts);
+			assert_eq!(texts(source, &trim), compiled, "{:?}", source);
+		}
+	}
+
+	#[test]
+	fn reload() {
+		// A copy, the test changes the template
+		let path = std::env::temp_dir()
+			.join(format!("t4rust-hot-reload-{}.tt", std::process::id()));
+		let source =
+			"<# for i in 0..self.count { #>\n  Item <#= i #>\n<# } #>\nDone\n";
+		let (_, blocks) = __t4rust_split(source).unwrap();
+		let hash = __t4rust_structure_hash(&blocks);
+		let reload = |source: &str| {
+			std::fs::write(&path, source).unwrap();
+			__t4rust_hot_reload(
+				path.to_str().unwrap(),
+				hash,
+				&[true, false, true],
+			)
+		};
+
+		assert_eq!(reload(source).unwrap(), ["", "  Item ", "\n", "Done\n"]);
+		// Text changes are visible on the next render
+		assert_eq!(
+			reload(
+				"<# for i in 0..self.count { #>\n- Entry <#= i #>;\n<# } \
+				 #>\nFin"
+			)
+			.unwrap(),
+			["", "- Entry ", ";\n", "Fin"]
+		);
+		// Changed blocks fall back to the compiled text
+		assert_eq!(
+			reload("<# for i in 0..1 { #>\n  Item <#= i #>\n<# } #>\n"),
+			None
+		);
+		std::fs::remove_file(&path).unwrap();
+	}
+}
@@ -0,0 +1,138 @@
+// The part of hot reloading which runs in the program, it is copied into the
+// generated code and may only use the standard library.
+
+/// Splits a template into its unescaped texts and its raw blocks like the
+/// parser, `None` if a block is not closed.
+fn __t4rust_split(
+	source: &str,
+) -> ::std::option::Option<(
+	::std::vec::Vec<::std::string::String>,
+	::std::vec::Vec<&str>,
+)>
+{
+	let mut texts = ::std::vec::Vec::new();
+	let mut blocks = ::std::vec::Vec::new();
+	let mut text = ::std::string::String::new();
+	let mut rest = source;
+	while let Some(start) = rest.find("<#") {
+		text.push_str(&rest[..start]);
+		rest = &rest[start..];
+		if rest.starts_with("<#<#") {
+			text.push_str("<#");
+			rest = &rest[4..];
+			continue;
+		}
+		let mut end = 2;
+		loop {
+			end += rest[end..].find("#>")? + 2;
+			// `#>#>` is an escaped block end
+			if !rest[end..].starts_with("#>") {
+				break;
+			}
+			end += 2;
+		}
+		texts.push(::std::mem::take(&mut text));
+		blocks.push(&rest[..end]);
+		rest = &rest[end..];
+	}
+	text.push_str(rest);
+	texts.push(text);
+	Some((texts, blocks))
+}
+
+/// Hashes the blocks of a template with FNV-1a, which is stable across
+/// compiler versions.
+fn __t4rust_structure_hash(blocks: &[&str]) -> u64 {
+	let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+	for block in blocks {
+		for byte in block.bytes().chain(::std::iter::once(0)) {
+			hash ^= u64::from(byte);
+			hash = hash.wrapping_mul(0x0100_0000_01b3);
+		}
+	}
+	hash
+}
+
+/// Removes the whitespace around the blocks like `cleanws`, `trim` tells for
+/// every block if it is cleaned.
+fn __t4rust_clean(texts: &mut [::std::string::String], trim: &[bool]) {
+	let is_space = |c: char| c == ' ' || c == '\t';
+	// The length of the whitespace after the line break at the end
+	let trailing = |text: &str| {
+		let rest = text.trim_end_matches(is_space);
+		if rest.ends_with('\n') { Some(text.len() - rest.len()) } else { None }
+	};
+	// The length of the whitespace until the first line break
+	let leading = |text: &str| {
+		let rest = text.trim_start_matches(is_space);
+		let spaces = text.len() - rest.len();
+		if rest.starts_with('\n') {
+			Some(spaces + 1)
+		} else if rest.starts_with("\r\n") {
+			Some(spaces + 2)
+		} else {
+			None
+		}
+	};
+
+	let mut cleaned = ::std::vec::Vec::new();
+	for (i, _) in trim.iter().enumerate().filter(|(_, trim)| **trim) {
+		let before = match trailing(&texts[i]) {
+			None if i == 0 && texts[0].is_empty() => Some(0),
+			before => before,
+		};
+		if let (Some(before), Some(after)) = (before, leading(&texts[i + 1])) {
+			cleaned.push((i, before, after));
+		}
+	}
+	for (i, before, after) in cleaned {
+		let len = texts[i].len();
+		texts[i].truncate(len - before);
+		texts[i + 1].drain(..after);
+	}
+}
+
+/// Reads the texts of a template in debug builds.
+///
+/// Returns `None` in release builds and if the blocks of the template
+/// changed, then a warning is printed once and the compiled texts are used.
+fn __t4rust_hot_reload(
+	path: &str,
+	hash: u64,
+	trim: &[bool],
+) -> ::std::option::Option<::std::vec::Vec<::std::string::String>>
+{
+	static WARNED: ::std::sync::atomic::AtomicBool =
+		::std::sync::atomic::AtomicBool::new(false);
+	if !cfg!(debug_assertions) {
+		return None;
+	}
+	let texts = ::std::fs::read_to_string(path).ok().and_then(|source| {
+		let (mut texts, blocks) = __t4rust_split(&source)?;
+		if __t4rust_structure_hash(&blocks) != hash {
+			return None;
+		}
+		__t4rust_clean(&mut texts, trim);
+		Some(texts)
+	});
+	if texts.is_none()
+		&& !WARNED.swap(true, ::std::sync::atomic::Ordering::Relaxed)
+	{
+		eprintln!(
+			"t4rust: The blocks of {} changed or it can not be read, the \
+			 compiled text is used until the crate is rebuilt",
+			path
+		);
+	}
+	texts
+}
+
+/// The reloaded text with `index` or the compiled text.
+fn __t4rust_text<'a>(
+	texts: &'a ::std::option::Option<::std::vec::Vec<::std::string::String>>,
+	index: usize,
+	compiled: &'a str,
+) -> &'a str
+{
+	texts.as_ref().map_or(compiled, |texts| texts[index].as_str())
+}
@@ -0,0 +1,158 @@
+//! A minimal Rust tokenizer for template code blocks.
+//!
+//! Code blocks are fragments like `for x in y {` which are not valid token
+//! streams on their own, so they can not be handed to `proc_macro2` or `syn`.
+//! This lexer only knows enough Rust to skip comments and literals and to
+//! split the rest into identifiers and punctuation.
+
+#[derive(Debug, PartialEq)]
+pub enum Token<'a> {
+	Ident(&'a str),
+	Punct(char),
+	Literal(&'a str),
+	Lifetime(&'a str),
+}
+
+/// Splits `code` into tokens, each paired with its byte offset in `code`.
+///
+/// Comments and whitespace are dropped. Unterminated literals or comments
+/// swallow the rest of the input.
+pub fn tokenize(code: &str) -> Vec<(usize, Token<'_>)> {
+	let bytes = code.as_bytes();
+	let mut tokens = Vec::new();
+	let mut i = 0;
+
+	while i < code.len() {
+		let rest = &code[i..];
+		let c = rest.chars().next().unwrap();
+
+		if c.is_whitespace() {
+			i += c.len_utf8();
+		} else if rest.starts_with("//") {
+			i += rest.find('\n').unwrap_or(rest.len());
+		} else if rest.starts_with("/*") {
+			i += block_comment_len(rest);
+		} else if let Some(len) = raw_string_len(rest) {
+			tokens.push((i, Token::Literal(&rest[..len])));
+			i += len;
+		} else if c == '"' || rest.starts_with("b\"") {
+			let len = quoted_len(rest, '"');
+			tokens.push((i, Token::Literal(&rest[..len])));
+			i += len;
+		} else if rest.starts_with("b'") {
+			let len = quoted_len(rest, '\'');
+			tokens.push((i, Token::Literal(&rest[..len])));
+			i += len;
+		} else if c == '\'' {
+			match char_literal_len(rest) {
+				Some(len) => {
+					tokens.push((i, Token::Literal(&rest[..len])));
+					i += len;
+				}
+				None => {
+					let len = 1 + ident_len(&rest[1..]);
+					tokens.push((i, Token::Lifetime(&rest[..len])));
+					i += len;
+				}
+			}
+		} else if rest.starts_with("r#")
+			&& is_ident_start(rest[2..].chars().next())
+		{
+			let len = 2 + ident_len(&rest[2..]);
+			tokens.push((i, Token::Ident(&rest[2..len])));
+			i += len;
+		} else if is_ident_start(Some(c)) {
+			let len = ident_len(rest);
+			tokens.push((i, Token::Ident(&rest[..len])));
+			i += len;
+		} else if c.is_ascii_digit() {
+			let mut len = ident_len(rest);
+			// Accept a fractional part, but not the start of a range `0..`
+			while bytes.get(i + len) == Some(&b'.')
+				&& bytes.get(i + len + 1).is_some_and(u8::is_ascii_digit)
+			{
+				len += 1 + ident_len(&rest[len + 1..]);
+			}
+			tokens.push((i, Token::Literal(&rest[..len])));
+			i += len;
+		} else {
+			tokens.push((i, Token::Punct(c)));
+			i += c.len_utf8();
+		}
+	}
+
+	tokens
+}
+
+fn is_ident_start(c: Option<char>) -> bool {
+	matches!(c, Some(c) if c == '_' || c.is_alphabetic())
+}
+
+fn ident_len(s: &str) -> usize {
+	s.find(|c: char| c != '_' && !c.is_alphanumeric()).unwrap_or(s.len())
+}
+
+fn block_comment_len(s: &str) -> usize {
+	let mut depth = 0;
+	let mut i = 0;
+	while i < s.len() {
+		if s[i..].starts_with("/*") {
+			depth += 1;
+			i += 2;
+		} else if s[i..].starts_with("*/") {
+			depth -= 1;
+			i += 2;
+			if depth == 0 {
+				return i;
+			}
+		} else {
+			i += s[i..].chars().next().unwrap().len_utf8();
+		}
+	}
+	s.len()
+}
+
+/// Length of a quoted literal with backslash escapes, including an optional
+/// `b` prefix.
+fn quoted_len(s: &str, quote: char) -> usize {
+	let start = if s.starts_with('b') { 2 } else { 1 };
+	let mut chars = s[start..].char_indices();
+	while let Some((i, c)) = chars.next() {
+		if c == '\\' {
+			chars.next();
+		} else if c == quote {
+			return start + i + 1;
+		}
+	}
+	s.len()
+}
+
+fn raw_string_len(s: &str) -> Option<usize> {
+	let prefix = if s.starts_with("br") {
+		2
+	} else if s.starts_with('r') {
+		1
+	} else {
+		return None;
+	};
+	let sharps = s[prefix..].len() - s[prefix..].trim_start_matches('#').len();
+	if !s[prefix + sharps..].starts_with('"') {
+		return None;
+	}
+	let body = prefix + sharps + 1;
+	let end = format!("\"{}", "#".repeat(sharps));
+	Some(s[body..].find(&end).map_or(s.len(), |p| body + p + end.len()))
+}
+
+/// Distinguishes `'a'` and `'\n'` from the lifetime `'a`.
+fn char_literal_len(s: &str) -> Option<usize> {
+	let mut chars = s.char_indices().skip(1);
+	match chars.next()? {
+		(_, '\\') => Some(quoted_len(s, '\'')),
+		(_, '\'') => None,
+		(_, _) => match chars.next() {
+			Some((i, '\'')) => Some(i + 1),
+			_ => None,
+		},
+	}
+}
@@ -0,0 +1,1581 @@
+//! The template compiler of [t4rust](https://github.com/ReSpeak/t4rust).
+//!
+//! It is used by the `t4rust-derive` macros, but can also generate the code of
+//! a template ahead of time from a build script. The generated files are
+//! ordinary Rust code which can be checked in, inspected and debugged.
+//!
+//! ```no_run
+//! // build.rs
+//! fn main() {
+//!     let out_dir = std::env::var("OUT_DIR").unwrap();
+//!     t4rust_build::compile("templates/report.tt", &out_dir).unwrap();
+//! }
+//! ```
+//!
+//! ```rust,ignore
+//! // src/report.rs
+//! pub struct Report {
+//!     pub title: String,
+//! }
+//!
+//! // Implements `Display` for `Report`
+//! include!(concat!(env!("OUT_DIR"), "/report.rs"));
+//! ```
+//!
+//! If the template declares its inputs with `parameter` directives, the file
+//! also contains the struct and its builder.
+//!
+//! The pipeline can also be used step by step: [`parse_all`] splits a template
+//! into its parts, [`parse_postprocess`] and [`parse_optimize`] clean them up
+//! and [`codegen`] generates the printing statements.
+
+#![allow(clippy::needless_doctest_main)]
+
+pub mod attributes;
+pub mod check;
+pub mod config;
+mod coverage;
+pub mod debug;
+mod errors;
+mod escape;
+mod hot_reload;
+pub mod lexer;
+pub mod parameters;
+pub mod policy;
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use std::path::PathBuf;
+use std::result::Result;
+use std::vec::Vec;
+
+use nom::{
+	character::complete::{line_ending, space0},
+	combinator::map,
+	sequence::tuple,
+	IResult,
+};
+use proc_macro2::{Ident, LineColumn, TokenStream};
+use quote::{format_ident, quote};
+use syn::Visibility;
+use t4rust_parser::{directives, NodeKind};
+
+use crate::config::CrateConfig;
+use crate::lexer::Token;
+use crate::TemplatePart::*;
+
+const FORMATTER_NAME: &str = "_fmt";
+/// The macro which marks the template lines in the code of [`expand`].
+const LINE_MARKER: &str = "__t4rust_line";
+
+/// An error while compiling a template.
+#[derive(Debug)]
+pub enum Error {
+	/// A template file could not be read or the generated code could not be
+	/// written.
+	Io(PathBuf, std::io::Error),
+	/// An error in a template, `path` is `None` for inline templates.
+	Template { path: Option<PathBuf>, line: usize, reason: String },
+	/// An invalid `[package.metadata.t4rust]` table in a manifest.
+	Config(PathBuf, String),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+			Error::Template { path: Some(path), line, reason } => {
+				write!(f, "{}:{}: {}", path.display(), line, reason)
+			}
+			Error::Template { path: None, line, reason } => {
+				write!(f, "<inline template>:{}: {}", line, reason)
+			}
+			Error::Config(path, reason) => {
+				write!(f, "{}: {}", path.display(), reason)
+			}
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+/// Compiles a template file into `out_dir` and returns the path of the
+/// generated file.
+///
+/// The generated code is for a struct named after the template file,
+/// `report.tt` becomes `Report`, see [`compile_for`] to choose the name.
+/// Paths are relative to the crate root or the template root configured in
+/// `[package.metadata.t4rust]`.
+pub fn compile(
+	template: impl AsRef<Path>,
+	out_dir: impl AsRef<Path>,
+) -> Result<PathBuf, Error>
+{
+	let template = template.as_ref();
+	let name = struct_name(template, None)?;
+	compile_for(template, &name.to_string(), out_dir)
+}
+
+/// Compiles a template file for the struct `name` into `out_dir` and returns
+/// the path of the generated file.
+///
+/// The file is named after the template, `report.html.tt` becomes
+/// `report.html.rs`. A line for cargo to rerun the build script when the
+/// template changes is printed.
+pub fn compile_for(
+	template: impl AsRef<Path>,
+	name: &str,
+	out_dir: impl AsRef<Path>,
+) -> Result<PathBuf, Error>
+{
+	let template = template.as_ref();
+	let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR")
+		.map_or_else(|| PathBuf::from("."), PathBuf::from);
+	let config = CrateConfig::load(&manifest_dir)?;
+	let name = struct_name(template, Some(name))?;
+
+	let code =
+		template_struct(&config, &syn::parse_quote! { pub }, &name, template)?;
+	let file = match parse_generated(code, template) {
+		Ok(file) => file,
+		// Only the line markers of `expand` locate the error in the template
+		Err(err) => {
+			let name = name.to_string();
+			let located = expand(&config, template, Some(&name)).err();
+			return Err(located.unwrap_or(err));
+		}
+	};
+
+	let file_name = template.file_name().ok_or_else(|| {
+		invalid_path(template, "The template path has no file name")
+	})?;
+	let out_path =
+		out_dir.as_ref().join(Path::new(file_name).with_extension("rs"));
+	std::fs::write(&out_path, prettyplease::unparse(&file))
+		.map_err(|err| Error::Io(out_path.clone(), err))?;
+	println!("cargo:rerun-if-changed={}", config.root.join(template).display());
+	Ok(out_path)
+}
+
+/// Generates the code of a template file like [`compile_for`] and returns it
+/// pretty-printed, with a comment before the statements of each template line.
+///
+/// The struct is named after the template file if `name` is `None`.
+///
+/// ```no_run
+/// use t4rust_build::config::CrateConfig;
+///
+/// let config = CrateConfig::load(".".as_ref())?;
+/// let code = t4rust_build::expand(&config, "report.tt".as_ref(), None)?;
+/// print!("{}", code);
+/// # Ok::<(), t4rust_build::Error>(())
+/// ```
+pub fn expand(
+	config: &CrateConfig,
+	template: &Path,
+	name: Option<&str>,
+) -> Result<String, Error>
+{
+	let name = struct_name(template, name)?;
+	let info = TemplateInfo { line_markers: true, ..config.info() };
+	let vis = syn::parse_quote! { pub };
+	let (code, _) = generate_struct(config, info, &vis, &name, template)?;
+	let file = parse_generated(code, template)?;
+	let code = prettyplease::unparse(&file);
+	let source = read_from_file(&resolve(config, template)?)?;
+	Ok(line_comments(&code, &source))
+}
+
+/// Replaces the line markers in pretty-printed code with comments that show
+/// the template line.
+fn line_comments(code: &str, source: &str) -> String {
+	let lines: Vec<_> = source.lines().collect();
+	let marker = format!("{}!(", LINE_MARKER);
+	let mut res = String::with_capacity(code.len());
+	for line in code.lines() {
+		let statement = line.trim_start();
+		let indent = &line[..line.len() - statement.len()];
+		let number = statement
+			.strip_prefix(&marker)
+			.and_then(|s| s.strip_suffix(");"))
+			.and_then(|s| s.parse::<usize>().ok());
+		match number {
+			Some(number) => {
+				res.push_str(indent);
+				res.push_str(&format!("// line {}", number));
+				let text = lines.get(number - 1).map_or("", |l| l.trim());
+				if !text.is_empty() {
+					res.push_str(": ");
+					res.push_str(text);
+				}
+			}
+			None => res.push_str(line),
+		}
+		res.push('\n');
+	}
+	res
+}
+
+/// Parses the generated code of the template file `path`.
+///
+/// A syntax error is reported at the line of the last line marker before it,
+/// or at the first line without line markers.
+fn parse_generated(code: TokenStream, path: &Path) -> Result<syn::File, Error> {
+	syn::parse2(code.clone()).map_err(|err| Error::Template {
+		path: Some(path.to_path_buf()),
+		line: marker_line(code, err.span().start()).unwrap_or(1),
+		reason: err.to_string(),
+	})
+}
+
+/// The template line of the last line marker in `code` before `position`.
+fn marker_line(code: TokenStream, position: LineColumn) -> Option<usize> {
+	let mut markers = Vec::new();
+	collect_markers(code, &mut markers);
+	let position = (position.line, position.column);
+	markers
+		.into_iter()
+		.filter(|(start, _)| *start <= position)
+		.max_by_key(|(start, _)| *start)
+		.map(|(_, line)| line)
+}
+
+/// Collects the position and template line of all line markers in `code`.
+fn collect_markers(
+	code: TokenStream,
+	markers: &mut Vec<((usize, usize), usize)>,
+)
+{
+	use proc_macro2::TokenTree;
+
+	let tokens: Vec<_> = code.into_iter().collect();
+	for (i, token) in tokens.iter().enumerate() {
+		match (token, tokens.get(i + 2)) {
+			(TokenTree::Ident(ident), Some(TokenTree::Group(args)))
+				if ident == LINE_MARKER =>
+			{
+				if let Ok(line) = args.stream().to_string().parse() {
+					let start = ident.span().start();
+					markers.push(((start.line, start.column), line));
+				}
+			}
+			(TokenTree::Group(group), _) => {
+				collect_markers(group.stream(), markers)
+			}
+			_ => {}
+		}
+	}
+}
+
+/// An error for a template path which can not be used.
+fn invalid_path(path: &Path, reason: &str) -> Error {
+	let err = std::io::Error::new(std::io::ErrorKind::InvalidInput, reason);
+	Error::Io(path.to_path_buf(), err)
+}
+
+/// The template path as string, the generated code needs UTF-8 paths.
+fn path_str(path: &Path) -> Result<&str, Error> {
+	let reason = "The template path is not valid UTF-8";
+	path.to_str().ok_or_else(|| invalid_path(path, reason))
+}
+
+/// The struct name for a template, `name` or derived from the file name.
+fn struct_name(template: &Path, name: Option<&str>) -> Result<Ident, Error> {
+	let error = |reason| Error::Template {
+		path: Some(template.to_path_buf()),
+		line: 1,
+		reason,
+	};
+	match name {
+		Some(name) => syn::parse_str::<Ident>(name).map_err(|_| {
+			error(format!("The struct name \"{}\" is no identifier", name))
+		}),
+		None => parameters::type_name(template).ok_or_else(|| {
+			error("Can not derive a struct name from the file name".into())
+		}),
+	}
+}
+
+/// Generates the `Display` implementation for a template file.
+///
+/// If the template declares `parameter` directives, the struct and its builder
+/// are generated as well.
+pub fn template_struct(
+	config: &CrateConfig,
+	vis: &Visibility,
+	name: &Ident,
+	path: &Path,
+) -> Result<TokenStream, Error>
+{
+	let (code, stages) =
+		generate_struct(config, config.info(), vis, name, path)?;
+	if let Some(stages) = stages {
+		let key =
+			debug::template_key(&config.root, &stages.path, &name.to_string());
+		write_artifacts(config, &key, &[stages], &code)?;
+	}
+	Ok(code)
+}
+
+/// Writes the [`debug`] artifacts of the struct with the `key` and returns
+/// the path of the pretty-printed `code`.
+pub fn write_artifacts(
+	config: &CrateConfig,
+	key: &Path,
+	stages: &[debug::Stages],
+	code: &TokenStream,
+) -> Result<PathBuf, Error>
+{
+	let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR")
+		.map_or_else(|| PathBuf::from("."), PathBuf::from);
+	let dir = debug::directory(&manifest_dir, key);
+	stages
+		.iter()
+		.try_for_each(|s| debug::write_parts(&dir, &config.root, s).map(drop))
+		.and_then(|_| debug::write_code(&dir, code))
+		.map_err(|err| Error::Io(dir, err))
+}
+
+fn generate_struct(
+	config: &CrateConfig,
+	initial: TemplateInfo,
+	vis: &Visibility,
+	name: &Ident,
+	path: &Path,
+) -> Result<(TokenStream, Option<debug::Stages>), Error>
+{
+	let path = resolve(config, path)?;
+	let source = read_from_file(&path)?;
+	let mut info = initial.clone();
+	let parts = parse_all(&mut info, &source).map_err(|err| {
+		Error::Template {
+			path: Some(path.clone()),
+			line: line_of(&source, err.index),
+			reason: err.reason,
+		}
+	})?;
+	let parameters = parameters::collect(&parts).map_err(|(line, reason)| {
+		Error::Template { path: Some(path.clone()), line, reason }
+	})?;
+
+	let template = compile_file(config, initial, None, &path)?;
+	let body = template_body(template.tokens);
+	let code = if parameters.is_empty() {
+		let fmt = formatter_ident();
+		quote! {
+			impl ::std::fmt::Display for #name {
+				fn fmt(&self, #fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+					#body
+				}
+			}
+		}
+	} else {
+		parameters::generate_struct(vis, name, &parameters, body)
+	};
+	let code = if config.errors {
+		let method = format_ident!("try_render");
+		let method = try_render_method(vis, &method, quote! { self });
+		quote! {
+			#code
+			impl #name {
+				#method
+			}
+		}
+	} else {
+		code
+	};
+	Ok((code, template.stages))
+}
+
+/// Wraps the printing statements of a template into the body of a `fmt`
+/// function, see [`template_view`].
+pub fn template_body(tokens: TokenStream) -> TokenStream {
+	let fmt = formatter_ident();
+	let view = template_view(tokens, TokenStream::new(), true);
+	quote! { ::std::fmt::Display::fmt(&#view, #fmt) }
+}
+
+/// Wraps the printing statements of a template into a closure, which is
+/// returned as `Display` adaptor.
+///
+/// `capture` is put in front of the closure, e.g. `move`. If `pretty` is set,
+/// the alternate flag of the formatter is available as `pretty`. Inline
+/// templates leave it unset, so that they can use a `pretty` of the caller.
+/// With a width, the template is rendered into a string first, which is then
+/// padded and aligned as a whole.
+pub fn template_view(
+	tokens: TokenStream,
+	capture: TokenStream,
+	pretty: bool,
+) -> TokenStream
+{
+	let fmt = formatter_ident();
+	let pretty = if pretty {
+		quote! {
+			#[allow(unused_variables)]
+			let pretty = #fmt.alternate();
+		}
+	} else {
+		TokenStream::new()
+	};
+	quote! {{
+		struct __T4rustView<F>(F);
+		impl<F> ::std::fmt::Display for __T4rustView<F>
+		where
+			F: Fn(&mut ::std::fmt::Formatter) -> ::std::fmt::Result,
+		{
+			fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+				if f.width().is_none() {
+					return (self.0)(f);
+				}
+				type Render<'a> = &'a dyn Fn(
+					&mut ::std::fmt::Formatter,
+				) -> ::std::fmt::Result;
+				let unpadded = __T4rustView(&self.0 as Render);
+				// `format!` would panic on errors of the template
+				let mut output = ::std::string::String::new();
+				if f.alternate() {
+					::std::fmt::Write::write_fmt(
+						&mut output,
+						::std::format_args!("{:#}", unpadded),
+					)?;
+				} else {
+					::std::fmt::Write::write_fmt(
+						&mut output,
+						::std::format_args!("{}", unpadded),
+					)?;
+				}
+				f.pad(&output)
+			}
+		}
+		__T4rustView(#capture |#fmt: &mut ::std::fmt::Formatter| -> ::std::fmt::Result {
+			#pretty
+			#[allow(unused_macros)]
+			macro_rules! out {
+				($($arg:tt)*) => { ::std::write!(#fmt, $($arg)*) };
+			}
+			#tokens
+			Ok(())
+		})
+	}}
+}
+
+/// A method which renders the `Display` adaptor `view` into a string or
+/// returns the render error, see the `errors` option of the crate
+/// configuration.
+pub fn try_render_method(
+	vis: &Visibility,
+	method: &Ident,
+	view: TokenStream,
+) -> TokenStream
+{
+	quote! {
+		#[allow(dead_code)]
+		#vis fn #method(&self) -> ::std::result::Result<
+			::std::string::String,
+			::t4rust_error::RenderError,
+		> {
+			::t4rust_error::render(&#view)
+		}
+	}
+}
+
+/// The generated code for a single template file.
+pub struct CompiledTemplate {
+	/// The statements which print the template to the formatter.
+	pub tokens: TokenStream,
+	pub debug_print: bool,
+	/// The code and expression blocks in the order of the template, `raw!`
+	/// is removed from expressions.
+	pub blocks: Vec<TemplatePart>,
+	/// The parts after each compilation step if `debug_print` is set.
+	pub stages: Option<debug::Stages>,
+}
+
+/// Resolves a template path relative to the template root of the crate.
+fn resolve(config: &CrateConfig, path: &Path) -> Result<PathBuf, Error> {
+	let path = config.root.join(path);
+	path.canonicalize().map_err(|err| Error::Io(path, err))
+}
+
+/// Reads, parses and generates the code for one template file.
+///
+/// `path` is relative to the template root of the crate, `escape` overrides
+/// the escape function the crate configures for the file.
+pub fn compile_file(
+	config: &CrateConfig,
+	mut info: TemplateInfo,
+	escape: Option<&str>,
+	path: &Path,
+) -> Result<CompiledTemplate, Error>
+{
+	let path = &resolve(config, path)?;
+
+	if let Some(escape) = escape.or_else(|| config.escape_for(path)) {
+		info.print_postprocessor = escape.to_string();
+	}
+	if let Some(markers) = &mut info.markers {
+		let root = config.root.canonicalize().unwrap_or_default();
+		let file = path.strip_prefix(root).unwrap_or(path);
+		*markers = markers.replace("{file}", &file.to_string_lossy());
+	}
+	// Read template file
+	let read = read_from_file(path)?;
+
+	let template = compile_source(&info, &read, Some(path))?;
+	let tokens = template.tokens;
+	let file = path_str(path)?;
+
+	Ok(CompiledTemplate {
+		tokens: quote! {
+			let _ = include_bytes!(#file);
+			#tokens
+		},
+		debug_print: template.debug_print,
+		blocks: template.blocks,
+		stages: template.stages,
+	})
+}
+
+/// Compiles the source of a template, `path` is the template file it was read
+/// from.
+pub fn compile_source(
+	initial: &TemplateInfo,
+	source: &str,
+	path: Option<&Path>,
+) -> Result<CompiledTemplate, Error>
+{
+	// Inline templates have no file to reload or report
+	let file = path.map(path_str).transpose()?;
+	let hot_reload = file.filter(|_| initial.hot_reload);
+	let coverage = file.filter(|_| initial.coverage);
+	let mut initial = TemplateInfo {
+		hot_reload: hot_reload.is_some(),
+		coverage: coverage.is_some(),
+		..initial.clone()
+	};
+	if let Some(markers) = &mut initial.markers {
+		let file = path.and_then(Path::file_name).map(Path::new);
+		let file = file.map_or("<inline>".into(), Path::to_string_lossy);
+		*markers = markers.replace("{file}", &file);
+	}
+	let initial = &initial;
+	let mut info = initial.clone();
+
+	// Parse template file
+	let mut data =
+		parse_all(&mut info, source).map_err(|err| Error::Template {
+			path: path.map(Path::to_path_buf),
+			line: line_of(source, err.index),
+			reason: err.reason,
+		})?;
+
+	if hot_reload.is_some() && !data.last().is_some_and(|p| p.kind.is_text()) {
+		// Every block is followed by a text which can be reloaded
+		let line = line_of(source, source.len());
+		data.push(Part { kind: Text(String::new()), line });
+	}
+	let parsed = info.debug_print.then(|| debug::parts(&data));
+
+	parse_postprocess(initial, &mut data);
+	let postprocessed = info.debug_print.then(|| debug::parts(&data));
+
+	// The reloaded texts are not merged
+	let prelude = hot_reload
+		.map(|path| hot_reload::prelude(initial, path, source, &data));
+	let data = if prelude.is_some() { data } else { parse_optimize(data) };
+
+	let stages = match (path, parsed, postprocessed) {
+		(Some(path), Some(parsed), Some(postprocessed)) => Some(debug::Stages {
+			path: path.to_path_buf(),
+			parsed,
+			postprocessed,
+			optimized: debug::parts(&data),
+		}),
+		_ => None,
+	};
+	let coverage = coverage.map(coverage::prelude);
+	let blocks = data
+		.iter()
+		.filter_map(|part| match &part.kind {
+			Code(code) => Some(Code(code.clone())),
+			Expr(expr) => {
+				let expr = escape::strip_raw_marker(expr).unwrap_or(expr);
+				Some(Expr(expr.to_string()))
+			}
+			Text(_) | Directive(_) => None,
+		})
+		.collect();
+	let mut tokens =
+		codegen(initial, data).map_err(|(line, reason)| Error::Template {
+			path: path.map(Path::to_path_buf),
+			line,
+			reason,
+		})?;
+	if initial.errors {
+		tokens = errors::wrap(file, tokens);
+	}
+	let tokens = quote! { #prelude #coverage #tokens };
+	Ok(CompiledTemplate {
+		tokens,
+		debug_print: info.debug_print,
+		blocks,
+		stages,
+	})
+}
+
+/// Generates the statements which print the parts of a template to the
+/// formatter `_fmt`.
+///
+/// Errors are returned with the line of the invalid part.
+pub fn codegen(
+	initial: &TemplateInfo,
+	data: Vec<Part>,
+) -> Result<TokenStream, (usize, String)>
+{
+	// Build code from template
+	let mut info = initial.clone();
+	let mut builder = String::new();
+	let mut escapers = BTreeSet::new();
+	let mut marked_line = 0;
+	let mut text_index = 0;
+	// Code blocks can end in the middle of a statement
+	let mut in_statement = false;
+	let mut blocks = Vec::new();
+	// The current line can only be set between statements
+	let mut after_output = true;
+	for part in &data {
+		if info.line_markers
+			&& part.line != marked_line
+			&& !in_statement
+			&& !matches!(part.kind, Directive(_))
+		{
+			builder.push_str(&format!("{}!({});\n", LINE_MARKER, part.line));
+			marked_line = part.line;
+		}
+		if info.errors
+			&& (after_output || !matches!(part.kind, Code(_)))
+			&& !matches!(part.kind, Directive(_))
+		{
+			builder.push_str(&errors::at(part.line));
+		}
+		match &part.kind {
+			Code(_) => after_output = false,
+			Directive(_) => {}
+			_ => after_output = true,
+		}
+		match &part.kind {
+			Code(code) => {
+				in_statement = !code.trim_end().ends_with(&['{', '}', ';'][..])
+			}
+			Directive(_) => {}
+			_ => in_statement = false,
+		}
+		let (before, after) = match (&info.markers, &part.kind) {
+			(Some(format), Text(x)) if !x.is_empty() => {
+				markers(format, part.line)
+			}
+			(Some(format), Expr(_)) => markers(format, part.line),
+			_ => Default::default(),
+		};
+		if !before.is_empty() {
+			builder.push_str(&generate_save_str_print(&before));
+		}
+		// Code is counted after it, when it ends a statement
+		let block = match &part.kind {
+			Text(x) if info.coverage && !x.is_empty() => {
+				builder.push_str(&coverage::hit(part.line));
+				blocks.push((part.line, coverage::end_line(part.line, x)));
+				None
+			}
+			Code(x) if info.coverage && !in_statement => {
+				blocks.push((part.line, coverage::end_line(part.line, x)));
+				Some(part.line)
+			}
+			_ => None,
+		};
+		match &part.kind {
+			Text(x) if info.hot_reload => {
+				builder.push_str(&format!(
+					"_fmt.write_str(__t4rust_text(&{}, {}, {}))?;\n",
+					hot_reload::TEXTS,
+					text_index,
+					raw_string(x)
+				));
+				text_index += 1;
+			}
+			Text(x) => {
+				builder.push_str(generate_save_str_print(x).as_ref());
+			}
+			Code(x) => {
+				builder.push_str(x.as_ref());
+			}
+			Expr(x) => {
+				if escape::builtin_function(&info.print_postprocessor).is_some()
+				{
+					escapers.insert(info.print_postprocessor.clone());
+				}
+				builder.push_str(generate_expression_print(x, &info).as_ref());
+			}
+			Directive(dir) => {
+				apply_directive(&mut info, dir)
+					.map_err(|reason| (part.line, reason))?;
+			}
+		}
+		if !after.is_empty() {
+			builder.push_str(&generate_save_str_print(&after));
+		}
+		if let Some(line) = block {
+			builder.push_str(&coverage::hit(line));
+		}
+	}
+	if !blocks.is_empty() {
+		builder.insert_str(0, &coverage::register(&blocks));
+	}
+
+	let tokens: TokenStream = builder.parse().map_err(|_| {
+		let reason = "Invalid Rust tokens, e.g. an unterminated literal";
+		let line = data
+			.iter()
+			.find(|part| !can_tokenize(&part.kind))
+			.map_or(1, |part| part.line);
+		(line, reason.to_string())
+	})?;
+	let tokens = make_formatter_hygienic(tokens);
+	let escapers =
+		escapers.iter().filter_map(|name| escape::builtin_definition(name));
+
+	Ok(quote! {
+		#(#escapers)*
+		#tokens
+	})
+}
+
+/// Checks if the code of a part can be split into Rust tokens.
+///
+/// Code blocks do not need to be balanced, so their delimiters are ignored.
+fn can_tokenize(part: &TemplatePart) -> bool {
+	let code = match part {
+		Code(code) | Expr(code) => code,
+		Text(_) | Directive(_) => return true,
+	};
+	let mut bytes = code.clone().into_bytes();
+	for (i, token) in lexer::tokenize(code) {
+		if matches!(token, Token::Punct('{' | '}' | '(' | ')' | '[' | ']')) {
+			bytes[i] = b' ';
+		}
+	}
+	String::from_utf8(bytes)
+		.is_ok_and(|code| code.parse::<TokenStream>().is_ok())
+}
+
+/// Splits the source marker `format` for a part on `line` into the texts
+/// before and after its output.
+///
+/// `{file}` and `{line}` in the format are replaced with the position of the
+/// part and `{output}` is the output. Without `{output}` the whole marker is
+/// printed before the output.
+///
+/// ```
+/// let markers = t4rust_build::markers("<!-- t4:{file}:{line} -->", 42);
+/// assert_eq!(markers, ("<!-- t4:{file}:42 -->".into(), String::new()));
+/// ```
+pub fn markers(format: &str, line: usize) -> (String, String) {
+	let format = format.replace("{line}", &line.to_string());
+	match format.split_once("{output}") {
+		Some((before, after)) => (before.to_string(), after.to_string()),
+		None => (format, String::new()),
+	}
+}
+
+/// The formatter binding of the generated `fmt` function.
+///
+/// It is created with a mixed-site span. The deprecated `_fmt` in template code
+/// is rebound to it as well, so [`parse_all`] rejects template code which
+/// binds `_fmt`.
+pub fn formatter_ident() -> proc_macro2::Ident {
+	proc_macro2::Ident::new(FORMATTER_NAME, proc_macro2::Span::mixed_site())
+}
+
+/// Moves all uses of the formatter into the hygienic context of the
+/// generated `fmt` function.
+///
+/// The generated print statements and the deprecated `_fmt` in template code
+/// both refer to the formatter by name, after parsing the code they are
+/// rebound here.
+fn make_formatter_hygienic(
+	tokens: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+	use proc_macro2::{Group, TokenTree};
+
+	tokens
+		.into_iter()
+		.map(|tree| match tree {
+			TokenTree::Ident(ref ident) if ident == FORMATTER_NAME => {
+				TokenTree::Ident(formatter_ident())
+			}
+			TokenTree::Group(group) => {
+				let mut new = Group::new(
+					group.delimiter(),
+					make_formatter_hygienic(group.stream()),
+				);
+				new.set_span(group.span());
+				TokenTree::Group(new)
+			}
+			tree => tree,
+		})
+		.collect()
+}
+
+fn generate_expression_print(print_expr: &str, info: &TemplateInfo) -> String {
+	let postprocessor = info.escape_function();
+	if let Some(raw) = escape::strip_raw_marker(print_expr) {
+		format!("write!(_fmt, \"{{}}\", {})?;\n", raw)
+	} else if postprocessor.is_empty() {
+		format!("write!(_fmt, \"{{}}\", {})?;\n", print_expr)
+	} else {
+		format!(
+			"{{
+			let _s = format!(\"{{}}\", {});
+			let _s_transfomed = {}(&_s);
+			_fmt.write_str(&_s_transfomed)?;
+			}}\n",
+			print_expr, postprocessor
+		)
+	}
+}
+
+fn generate_save_str_print(print_str: &str) -> String {
+	format!("_fmt.write_str({})?;\n", raw_string(print_str))
+}
+
+/// A raw string literal with enough `#` for its content.
+fn raw_string(print_str: &str) -> String {
+	let mut max_sharp_count = 0;
+	let mut cur_sharp_count = 0;
+
+	for c in print_str.chars() {
+		if c == '#' {
+			cur_sharp_count += 1;
+			max_sharp_count = std::cmp::max(max_sharp_count, cur_sharp_count);
+		} else {
+			cur_sharp_count = 0;
+		}
+	}
+
+	let sharps = "#".repeat(max_sharp_count + 1);
+	format!("r{1}\"{0}\"{1}", print_str, sharps)
+}
+
+/// Returns the 1-based line number of a byte index in `input`.
+pub fn line_of(input: &str, index: usize) -> usize {
+	input[..index.min(input.len())].matches('\n').count() + 1
+}
+
+fn read_from_file(path: &Path) -> Result<String, Error> {
+	let read = || -> std::io::Result<String> {
+		let mut file = File::open(path)?;
+		let mut contents = String::new();
+		file.read_to_string(&mut contents)?;
+		Ok(contents)
+	};
+	read().map_err(|err| Error::Io(path.to_path_buf(), err))
+}
+
+/// Transforms template code into an intermediate representation
+pub fn parse_all(
+	info: &mut TemplateInfo,
+	input: &str,
+) -> Result<Vec<Part>, TemplateError>
+{
+	let mut builder: Vec<Part> = Vec::new();
+	let mut brackets = Vec::new();
+	let mut code_blocks = Vec::new();
+	let mut line = 1;
+	let mut line_start = 0;
+
+	let template = t4rust_parser::parse(input)
+		.map_err(|err| TemplateError { index: err.index, reason: err.reason })?;
+
+	for node in template.nodes {
+		let index = node.span.start;
+		line += input[line_start..index].matches('\n').count();
+		line_start = index;
+		let part = |kind| Part { kind, line };
+		// Every block follows a (maybe empty) text part
+		if node.block().is_some()
+			&& !builder.last().is_some_and(|p| p.kind.is_text())
+		{
+			builder.push(part(Text(String::new())));
+		}
+
+		match node.kind {
+			NodeKind::Text { value } => {
+				builder.push(part(Text(value)));
+			}
+			NodeKind::Expr(block) => {
+				info.policy.check_expression(&block.value, index)?;
+				if info.strict {
+					check_strict_expression(info, &block.value, index)?;
+				}
+				builder.push(part(Expr(block.value)));
+			}
+			NodeKind::Directive(dir) => {
+				let dir = TemplateDirective {
+					name: dir.name.value,
+					params: dir
+						.params
+						.into_iter()
+						.map(|p| (p.name.value, p.value.value))
+						.collect(),
+				};
+				apply_directive(info, &dir)
+					.map_err(|reason| TemplateError { index, reason })?;
+				if info.strict
+					&& dir.name == "escape"
+					&& info.escape_function().is_empty()
+				{
+					return Err(TemplateError {
+						index,
+						reason: "Escaping can not be disabled in strict mode"
+							.into(),
+					});
+				}
+				builder.push(part(Directive(dir)));
+			}
+			NodeKind::Code(block) => {
+				let offset = block.content.start;
+				let content = block.value;
+				check_brackets(input, &content, offset, &mut brackets)?;
+				code_blocks.push((offset, content.clone()));
+				info.policy.check(&content, offset)?;
+				if info.strict {
+					check_strict_formatter_access(&content, offset)?;
+				}
+				builder.push(part(Code(content)));
+			}
+		}
+	}
+
+	if let Some((open, index)) = brackets.pop() {
+		return Err(TemplateError {
+			index,
+			reason: format!("Unclosed '{}' opened in this code block", open),
+		});
+	}
+	check_formatter_shadowing(&code_blocks)?;
+
+	Result::Ok(builder)
+}
+
+/// Tracks `{`, `(` and `[` nesting of a code block, the open brackets are
+/// carried over to the next code block in `stack`.
+///
+/// `offset` is the position of `code` in the template `input`, so that errors
+/// point at the block that contains the unmatched bracket.
+fn check_brackets(
+	input: &str,
+	code: &str,
+	offset: usize,
+	stack: &mut Vec<(char, usize)>,
+) -> Result<(), TemplateError>
+{
+	for (pos, token) in lexer::tokenize(code) {
+		let index = offset + pos;
+		match token {
+			Token::Punct(c @ ('{' | '(' | '[')) => {
+				stack.push((c, index));
+			}
+			Token::Punct(c @ ('}' | ')' | ']')) => {
+				let expected = match c {
+					'}' => '{',
+					')' => '(',
+					_ => '[',
+				};
+				match stack.pop() {
+					Some((open, _)) if open == expected => {}
+					Some((open, open_index)) => {
+						return Err(TemplateError {
+							index,
+							reason: format!(
+								"Found '{}' but the '{}' opened on line {} is \
+								 still unclosed",
+								c,
+								open,
+								line_of(input, open_index)
+							),
+						});
+					}
+					None => {
+						return Err(TemplateError {
+							index,
+							reason: format!("Unexpected closing '{}'", c),
+						});
+					}
+				}
+			}
+			_ => {}
+		}
+	}
+	Ok(())
+}
+
+/// Rejects code which binds a new variable with the name of the formatter.
+///
+/// Such a binding would hide the formatter from all following text and
+/// expression blocks. The code `blocks` and their positions in the template
+/// are parsed together, so that bindings in every kind of pattern are found,
+/// e.g. `if let Some(_fmt) = ...` or `|a, _fmt| ...`.
+fn check_formatter_shadowing(
+	blocks: &[(usize, String)],
+) -> Result<(), TemplateError>
+{
+	use syn::visit::{self, Visit};
+
+	const MARKER: &str = "__t4rust_fmt_";
+
+	struct Bindings(Vec<usize>);
+
+	impl<'ast> Visit<'ast> for Bindings {
+		fn visit_pat_ident(&mut self, pat: &'ast syn::PatIdent) {
+			let name = pat.ident.to_string();
+			if let Some(Ok(use_index)) =
+				name.strip_prefix(MARKER).map(str::parse)
+			{
+				self.0.push(use_index);
+			}
+			visit::visit_pat_ident(self, pat);
+		}
+
+		// Nested items can not see the formatter anyway
+		fn visit_item(&mut self, _: &'ast syn::Item) {}
+	}
+
+	// Every use of the formatter gets a unique name, which tells the position
+	// of a binding
+	let mut uses = Vec::new();
+	let mut code = String::from("{");
+	for (offset, block) in blocks {
+		let mut copied = 0;
+		for (pos, token) in lexer::tokenize(block) {
+			if token == Token::Ident(FORMATTER_NAME) {
+				code.push_str(&block[copied..pos]);
+				code.push_str(&format!("{}{}", MARKER, uses.len()));
+				uses.push(offset + pos);
+				copied = pos + FORMATTER_NAME.len();
+			}
+		}
+		code.push_str(&block[copied..]);
+		code.push('\n');
+	}
+	code.push('}');
+	if uses.is_empty() {
+		return Ok(());
+	}
+
+	// Code which does not parse on its own is reported by the compiler
+	let block = match syn::parse_str::<syn::Block>(&code) {
+		Ok(block) => block,
+		Err(_) => return Ok(()),
+	};
+	let mut bindings = Bindings(Vec::new());
+	bindings.visit_block(&block);
+	match bindings.0.into_iter().min() {
+		Some(use_index) => Err(TemplateError {
+			index: uses[use_index],
+			reason: format!(
+				"`{0}` is reserved for the template formatter and can not be \
+				 rebound, use `out!(...)` to write to it or choose another \
+				 name",
+				FORMATTER_NAME
+			),
+		}),
+		None => Ok(()),
+	}
+}
+
+/// In strict mode every expression must be escaped, unless it is explicitly
+/// marked with `raw!(...)`.
+fn check_strict_expression(
+	info: &TemplateInfo,
+	expr: &str,
+	index: usize,
+) -> Result<(), TemplateError>
+{
+	check_strict_formatter_access(expr, index + 3)?;
+	if info.escape_function().is_empty()
+		&& escape::strip_raw_marker(expr).is_none()
+	{
+		return Err(TemplateError {
+			index,
+			reason: "Expressions must be escaped in strict mode, set an \
+			         escape function or mark the expression with `raw!(...)`"
+				.into(),
+		});
+	}
+	Ok(())
+}
+
+/// Rejects writes to the formatter which would bypass the escape function.
+fn check_strict_formatter_access(
+	code: &str,
+	offset: usize,
+) -> Result<(), TemplateError>
+{
+	let tokens = lexer::tokenize(code);
+	for (i, (pos, token)) in tokens.iter().enumerate() {
+		let bypass = match token {
+			Token::Ident(FORMATTER_NAME) => FORMATTER_NAME,
+			Token::Ident("out")
+				if matches!(tokens.get(i + 1), Some((_, Token::Punct('!')))) =>
+			{
+				"out!"
+			}
+			_ => continue,
+		};
+		return Err(TemplateError {
+			index: offset + pos,
+			reason: format!(
+				"Writing to the formatter with `{}` is not allowed in strict \
+				 mode, use `<#= raw!(...) #>` for unescaped output",
+				bypass
+			),
+		});
+	}
+	Ok(())
+}
+
+/// Merges multiple identical Parts into one
+///
+/// Consecutive text and code parts are joined and keep the line of the first
+/// part, empty parts are removed.
+pub fn parse_optimize(data: Vec<Part>) -> Vec<Part> {
+	let mut combined = Vec::<Part>::new();
+	for part in data {
+		match (&part.kind, combined.last_mut().map(|p| &mut p.kind)) {
+			(Text(u) | Code(u) | Expr(u), _) if u.is_empty() => {}
+			(Text(u), Some(Text(last))) | (Code(u), Some(Code(last))) => {
+				last.push_str(u)
+			}
+			_ => combined.push(part),
+		}
+	}
+	combined
+}
+
+/// Applies template directives like 'cleanws' and modifies the input
+/// accordingly.
+pub fn parse_postprocess(initial: &TemplateInfo, data: &mut [Part]) {
+	let mut info = initial.clone();
+	let mut was_b_clean = None;
+	let mut clean_index = 0;
+
+	// if there are less than 3 blocks available we can't do any transformations
+	if data.len() < 3 {
+		return;
+	}
+
+	for i in 0..(data.len() - 2) {
+		let tri = data[i..(i + 3)].as_mut();
+		if let Directive(ref dir) = tri[1].kind {
+			// Directives are validated by `parse_all`
+			let _ = apply_directive(&mut info, dir);
+		}
+
+		if !info.clean_whitespace
+			|| !tri[0].kind.is_text()
+			|| !tri[1].kind.should_trim_whitespace()
+			|| !tri[2].kind.is_text()
+		{
+			continue;
+		}
+
+		let mut res_a = None;
+		if clean_index == i && was_b_clean.is_some() {
+			res_a = was_b_clean;
+		} else if let Text(ref text_a) = tri[0].kind {
+			let rev_txt: String = text_a.chars().rev().collect();
+			if let Ok((_, a_len)) = is_ws_till_newline(&rev_txt) {
+				res_a = Some(a_len);
+			} else if i == 0 && text_a.is_empty() {
+				// Start of file
+				res_a = Some((0, 0));
+			} else {
+				continue;
+			}
+		}
+
+		let mut res_b = None;
+		if let Text(ref text_b) = tri[2].kind {
+			if let Ok((_, b_len)) = is_ws_till_newline(text_b) {
+				res_b = Some(b_len);
+			} else {
+				continue;
+			}
+		}
+
+		// start trimming
+
+		if let Text(ref mut text_a) = tri[0].kind {
+			let res_a = res_a.unwrap();
+			let len = text_a.len();
+			text_a.drain((len - (res_a.0))..len);
+		}
+
+		if let Text(ref mut text_b) = tri[2].kind {
+			let rev_txt: String = text_b.chars().rev().collect();
+			if let Ok((_, b_len)) = is_ws_till_newline(&rev_txt) {
+				was_b_clean = Some(b_len);
+				clean_index = i + 2;
+			}
+
+			let res_b = res_b.unwrap();
+			text_b.drain(0..(res_b.0 + res_b.1));
+			// The text now starts on the next line
+			tri[2].line += 1;
+		}
+	}
+}
+
+/// Applies the options of a directive to `info`.
+///
+/// Unknown directives or options and invalid values are returned as error,
+/// the directives and their options are those of
+/// [`t4rust_parser::directives::DIRECTIVES`].
+fn apply_directive(
+	info: &mut TemplateInfo,
+	directive: &TemplateDirective,
+) -> Result<(), String>
+{
+	let name = directive.name.as_str();
+	let params = directive.params.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+	directives::check(name, params)?;
+	for (key, value) in &directive.params {
+		let flag = value == "true";
+		match (name, key.as_str()) {
+			("template", "debug") => info.debug_print = flag,
+			("template", "cleanws") | ("template", "clean_whitespace") => {
+				info.clean_whitespace = flag
+			}
+			("escape", "function") => {
+				info.print_postprocessor = value.to_string()
+			}
+			// A template can enable strict mode, but not disable it
+			("template", "strict") => info.strict |= flag,
+			// Parameters only declare the fields of generated structs
+			_ => {}
+		}
+	}
+	Ok(())
+}
+
+// NOM DECLARATIONS ===========================================================
+
+fn is_ws_till_newline(s: &str) -> IResult<&str, (usize, usize)> {
+	map(
+		tuple((space0, line_ending)),
+		|t: (&str, &str)| (t.0.len(), t.1.len()),
+	)(s)
+}
+
+// NOM END ====================================================================
+
+/// An error in a template, `index` is the byte position in the source.
+#[derive(Debug)]
+pub struct TemplateError {
+	pub reason: String,
+	pub index: usize,
+}
+
+/// A directive block, e.g. `<#@ escape function="html" #>`.
+#[derive(Debug)]
+pub struct TemplateDirective {
+	pub name: String,
+	pub params: Vec<(String, String)>,
+}
+
+/// A block of a template.
+#[derive(Debug)]
+pub enum TemplatePart {
+	Text(String),
+	Code(String),
+	Expr(String),
+	Directive(TemplateDirective),
+}
+
+/// A part of a template and the line where it starts.
+#[derive(Debug)]
+pub struct Part {
+	pub kind: TemplatePart,
+	/// The 1-based line in the template source.
+	pub line: usize,
+}
+
+impl TemplatePart {
+	fn is_text(&self) -> bool { matches!(self, Text(_)) }
+
+	/// Whitespace should only be trimmed for code and directive blocks, we want to keep it for
+	/// expressions.
+	fn should_trim_whitespace(&self) -> bool { matches!(self, Code(_) | Directive(_)) }
+}
+
+/// The settings of a template, directives change them while it is parsed.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateInfo {
+	pub debug_print: bool,
+	pub clean_whitespace: bool,
+	pub print_postprocessor: String,
+	/// Refuse all unescaped output which is not marked with `raw!(...)`.
+	pub strict: bool,
+	pub policy: policy::CodePolicy,
+	/// Marks the statements of each template line for [`expand`].
+	pub line_markers: bool,
+	/// Reload the texts from the template file in debug builds.
+	pub hot_reload: bool,
+	/// Printed around the output of each text and expression part, see
+	/// [`markers`].
+	pub markers: Option<String>,
+	/// Record the rendered blocks in the registry of `t4rust-coverage` in
+	/// builds with the `t4rust_coverage` cfg.
+	pub coverage: bool,
+	/// Report failures with the template line to `t4rust-error`.
+	pub errors: bool,
+}
+
+impl TemplateInfo {
+	/// The function which is called for expressions, resolves the names of
+	/// built-in escapers. Empty if escaping is disabled.
+	pub fn escape_function(&self) -> &str {
+		escape::builtin_function(&self.print_postprocessor)
+			.unwrap_or(&self.print_postprocessor)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse_error(input: &str) -> (usize, String) {
+		let err = parse_all(&mut TemplateInfo::default(), input).unwrap_err();
+		(line_of(input, err.index), err.reason)
+	}
+
+	#[test]
+	fn unclosed_bracket_points_at_opening_block() {
+		let (line, reason) = parse_error(
+			"Items:\n<# for i in 0..3 { #>\n<# if i > 1 { #>\nbig\n<# } #>\n",
+		);
+		assert_eq!(line, 2);
+		assert_eq!(reason, "Unclosed '{' opened in this code block");
+	}
+
+	#[test]
+	fn mismatched_bracket() {
+		let (line, reason) =
+			parse_error("<# let v = vec![1, 2; #>\n<#= v.len() #>\n<# ) #>");
+		assert_eq!(line, 3);
+		assert_eq!(
+			reason,
+			"Found ')' but the '[' opened on line 1 is still unclosed"
+		);
+	}
+
+	#[test]
+	fn unexpected_closing_bracket() {
+		let (line, reason) = parse_error("text\n\n<# } #>");
+		assert_eq!(line, 3);
+		assert_eq!(reason, "Unexpected closing '}'");
+	}
+
+	#[test]
+	fn formatter_shadowing() {
+		let (line, reason) = parse_error("a\n<# let mut _fmt = 5; #>");
+		assert_eq!(line, 2);
+		assert!(reason.starts_with("`_fmt` is reserved"));
+
+		let (line, _) = parse_error("<# x.map(|_fmt| 1); #>");
+		assert_eq!(line, 1);
+
+		let patterns = [
+			"<# let (a, _fmt) = (1, 2); #>",
+			"<# if let Some(_fmt) = x { #>a<# } #>",
+			"<# match x { #>\n<# Some(_fmt) => {} #>\n<# _ => {} } #>",
+			"<# let Point { x, y: _fmt } = p; #>",
+			"<# x.map(|a, _fmt| 1); #>",
+		];
+		for input in &patterns {
+			let err = parse_all(&mut TemplateInfo::default(), input)
+				.expect_err(input);
+			assert!(err.reason.starts_with("`_fmt` is reserved"), "{}", input);
+			assert_eq!(&input[err.index..err.index + 4], "_fmt");
+		}
+
+		// Uses and nested functions are fine
+		let ok = "<# out!(\"a\")?; _fmt.write_str(\"b\")?; #>\n\
+		          <# fn f(_fmt: u8) {} #>";
+		assert!(parse_all(&mut TemplateInfo::default(), ok).is_ok());
+	}
+
+	fn strict_error(input: &str) -> (usize, String) {
+		let mut info = TemplateInfo {
+			strict: true,
+			print_postprocessor: "html".into(),
+			..TemplateInfo::default()
+		};
+		let err = parse_all(&mut info, input).unwrap_err();
+		(line_of(input, err.index), err.reason)
+	}
+
+	#[test]
+	fn strict_mode() {
+		let (line, reason) =
+			strict_error("<#= a #>\n<#@ escape function=\"\" #>");
+		assert_eq!(line, 2);
+		assert_eq!(reason, "Escaping can not be disabled in strict mode");
+
+		let (line, reason) = strict_error("\n<# write!(_fmt, \"{}\", a)?; #>");
+		assert_eq!(line, 2);
+		assert!(reason.starts_with("Writing to the formatter with `_fmt`"));
+
+		let (_, reason) = strict_error("<#= { out!(\"x\")?; 1 } #>");
+		assert!(reason.starts_with("Writing to the formatter with `out!`"));
+
+		let mut info = TemplateInfo { strict: true, ..TemplateInfo::default() };
+		let err = parse_all(&mut info, "<#= raw!(a) #><#= a #>").unwrap_err();
+		assert_eq!(err.index, 14);
+		assert!(err.reason.starts_with("Expressions must be escaped"));
+	}
+
+	#[test]
+	fn restricted_policy() {
+		let mut info = TemplateInfo::default();
+		info.policy.restricted = true;
+		let err = parse_all(&mut info, "a\n<# /* ok */ #>\n<# let x = 1; #>")
+			.unwrap_err();
+		assert_eq!(line_of("a\n<# /* ok */ #>\n", err.index), 3);
+		assert!(err.reason.starts_with("Code blocks are not allowed"));
+
+		info.policy.allowed.push(policy::Construct::If);
+		assert!(parse_all(&mut info, "<# if a { #>x<# } else { #>y<# } #>")
+			.is_ok());
+		assert!(parse_all(
+			&mut info,
+			"<# if let Some(x) = self.a.get(1 + 2) { #><#= x.len() #><# } #>"
+		)
+		.is_ok());
+		for code in &[
+			"<# for x in y { #><# } #>",
+			"<# if { f(); true } { #><# } #>",
+			"<# if (|| true)() { #><# } #>",
+			"<# if std::process::exit(0) { #><# } #>",
+			"<# if a.map(|x| f(x)).is_some() { #><# } #>",
+			"<# if let Some(x) = f() { #><# } #>",
+			"<# if a { #><# } else if f() { #><# } #>",
+			"<# if m!() { #><# } #>",
+		] {
+			let err = parse_all(&mut info, code).unwrap_err();
+			assert!(err.reason.starts_with("Only `if cond {`"), "{}", code);
+		}
+
+		info.policy.allowed.push(policy::Construct::For);
+		let code = "<# for x in &self.a { #><# } #>";
+		assert!(parse_all(&mut info, code).is_ok());
+		for code in &[
+			"<# for x in (|| f())() { #><# } #>",
+			"<# for x in { f(); y } { #><# } #>",
+			"<# for x in f() { #><# } #>",
+		] {
+			let err = parse_all(&mut info, code).unwrap_err();
+			assert!(err.reason.starts_with("Only `if cond {`"), "{}", code);
+		}
+
+		for code in &[
+			"a\n<#= { f(); 1 } #>",
+			"a\n<#= (|| f())() #>",
+			"a\n<#= f() #>",
+			"a\n<#= std::process::exit(0) #>",
+			"a\n<#= format!(\"{}\", 1) #>",
+			"a\n<#= raw!({ f(); 1 }) #>",
+		] {
+			let err = parse_all(&mut info, code).unwrap_err();
+			assert_eq!(line_of(code, err.index), 2, "{}", code);
+			assert!(err.reason.starts_with("Only fields"), "{}", code);
+		}
+		assert!(parse_all(&mut info, "<#= raw!(self.a[0].trim()) #>").is_ok());
+	}
+
+	#[test]
+	fn invalid_directives() {
+		for (code, reason) in &[
+			(
+				"a\n<#@ template cleanws=\"yes\" #>",
+				"Expected `true` or `false` for `cleanws`",
+			),
+			(
+				"a\n<#@ template language=\"C#\" #>",
+				"Unknown parameter `language` for the `template` directive",
+			),
+			("a\n<#@ include file=\"b.tt\" #>", "Unknown directive `include`"),
+			(
+				"a\n<#@ escape #>",
+				"The `escape` directive needs the parameter `function`",
+			),
+		] {
+			let err =
+				parse_all(&mut TemplateInfo::default(), code).unwrap_err();
+			assert_eq!(line_of(code, err.index), 2, "{}", code);
+			assert_eq!(&err.reason, reason);
+		}
+	}
+
+	#[test]
+	fn invalid_tokens() {
+		let source = "<# for i in 0..2 { #>\n<#= i #>\n<#= \"abc #>\n<# } #>";
+		match compile_source(&TemplateInfo::default(), source, None) {
+			Err(Error::Template { path: None, line: 3, reason }) => {
+				assert!(reason.starts_with("Invalid Rust tokens"))
+			}
+			res => panic!("Expected an error on line 3, got {:?}", res.err()),
+		}
+	}
+
+	#[test]
+	fn optimize_keeps_order_and_lines() {
+		let mut info =
+			TemplateInfo { clean_whitespace: true, ..Default::default() };
+		let mut parts = parse_all(
+			&mut info,
+			"a\n<#= x #><#@ escape function=\"html\" #>\n<# f(); #>\nb<# \
+			 g(); #>",
+		)
+		.unwrap();
+		parse_postprocess(&info, &mut parts);
+		let parts: Vec<_> = parse_optimize(parts)
+			.into_iter()
+			.map(|p| (p.line, format!("{:?}", p.kind)))
+			.collect();
+		assert_eq!(
+			parts,
+			[
+				(1, "Text(\"a\\n\")".into()),
+				(2, "Expr(\" x \")".into()),
+				(2, "Directive(TemplateDirective { name: \"escape\", params: \
+				     [(\"function\", \"html\")] })"
+					.into()),
+				(2, "Text(\"\\n\")".into()),
+				(3, "Code(\" f(); \")".into()),
+				(4, "Text(\"b\")".into()),
+				(4, "Code(\" g(); \")".into()),
+			]
+		);
+	}
+
+	#[test]
+	fn brackets_in_literals_and_comments() {
+		let input = "<# let _ = (\"}\", '}', r#\"]\"#); // {\n/* ( */ #>";
+		assert!(parse_all(&mut TemplateInfo::default(), input).is_ok());
+	}
+}
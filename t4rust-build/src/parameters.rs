@@ -0,0 +1,257 @@
+//! Generates the data struct of a template from its parameter directives.
+//!
+//! ```text
+//! <#@ parameter name="title" type="String" #>
+//! <#@ parameter name="count" type="u32" default="0" #>
+//! ```
+
+use std::path::Path;
+
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+use syn::{Expr, Type, Visibility};
+
+use crate::{Part, TemplateDirective, TemplatePart};
+
+pub const PARAMETER_DIRECTIVE: &str = "parameter";
+
+/// An input of a template, declared with a `parameter` directive.
+pub struct Parameter {
+	pub name: Ident,
+	pub ty: Type,
+	pub default: Option<Expr>,
+}
+
+impl Parameter {
+	fn parse(directive: &TemplateDirective) -> Result<Self, String> {
+		let mut name = None;
+		let mut ty = None;
+		let mut default = None;
+		for (key, value) in &directive.params {
+			match key.as_str() {
+				"name" => {
+					name =
+						Some(syn::parse_str::<Ident>(value).map_err(|_| {
+							format!(
+								"The parameter name \"{}\" is no identifier",
+								value
+							)
+						})?)
+				}
+				"type" => {
+					ty = Some(syn::parse_str::<Type>(value).map_err(|err| {
+						format!("Invalid parameter type \"{}\": {}", value, err)
+					})?)
+				}
+				"default" => {
+					default =
+						Some(syn::parse_str::<Expr>(value).map_err(|err| {
+							format!(
+								"Invalid parameter default \"{}\": {}",
+								value, err
+							)
+						})?)
+				}
+				_ => {
+					return Err(format!(
+						"Unrecognized option \"{}\" of the parameter directive",
+						key
+					));
+				}
+			}
+		}
+
+		let name = name.ok_or("A parameter needs a name")?;
+		let ty =
+			ty.ok_or_else(|| format!("The parameter {} needs a type", name))?;
+		Ok(Self { name, ty, default })
+	}
+}
+
+/// Collects all parameters declared in a template.
+///
+/// Errors are returned with the line of the invalid directive.
+pub fn collect(
+	parts: &[Part],
+) -> Result<Vec<Parameter>, (usize, String)>
+{
+	let mut res: Vec<Parameter> = Vec::new();
+	for part in parts {
+		let directive = match &part.kind {
+			TemplatePart::Directive(dir) if dir.name == PARAMETER_DIRECTIVE => {
+				dir
+			}
+			_ => continue,
+		};
+		let parameter =
+			Parameter::parse(directive).map_err(|err| (part.line, err))?;
+		if res.iter().any(|p| p.name == parameter.name) {
+			return Err((
+				part.line,
+				format!("The parameter {} is declared twice", parameter.name),
+			));
+		}
+		res.push(parameter);
+	}
+	Ok(res)
+}
+
+/// The name of the struct for a template file, `Report` for
+/// `report.html.tt` or `SignUp` for `sign_up.tt`.
+pub fn type_name(path: &Path) -> Option<Ident> {
+	let stem = path.file_name()?.to_str()?.split('.').next()?;
+	let name: String = stem
+		.split(['_', '-', ' '])
+		.flat_map(|word| {
+			let mut chars = word.chars();
+			chars
+				.next()
+				.map(|c| c.to_uppercase().chain(chars.collect::<Vec<_>>()))
+				.into_iter()
+				.flatten()
+		})
+		.collect();
+	syn::parse_str::<Ident>(&name).ok()
+}
+
+/// Generates the struct with one public field per parameter and its builder.
+///
+/// `display` is the body of the `fmt` function for the struct.
+pub fn generate_struct(
+	vis: &Visibility,
+	name: &Ident,
+	parameters: &[Parameter],
+	display: TokenStream,
+) -> TokenStream
+{
+	let builder = format_ident!("{}Builder", name);
+	let names: Vec<_> = parameters.iter().map(|p| &p.name).collect();
+	let types: Vec<_> = parameters.iter().map(|p| &p.ty).collect();
+	let values = parameters.iter().map(|p| {
+		let name = &p.name;
+		let missing = name.to_string();
+		match &p.default {
+			Some(default) => quote! { self.#name.unwrap_or_else(|| #default) },
+			None => quote! {
+				match self.#name {
+					Some(value) => value,
+					None => return Err(#missing),
+				}
+			},
+		}
+	});
+	let fmt = crate::formatter_ident();
+
+	// A struct where all parameters have defaults can be created with
+	// `Default::default()`.
+	let default = if parameters.iter().all(|p| p.default.is_some()) {
+		let defaults = parameters.iter().map(|p| &p.default);
+		Some(quote! {
+			impl ::std::default::Default for #name {
+				fn default() -> Self {
+					Self { #(#names: #defaults),* }
+				}
+			}
+		})
+	} else {
+		None
+	};
+	let builder_doc = format!("Builder for [`{}`].", name);
+
+	quote! {
+		#vis struct #name {
+			#(pub #names: #types,)*
+		}
+
+		impl #name {
+			#[allow(dead_code)]
+			#vis fn builder() -> #builder {
+				#builder::default()
+			}
+		}
+
+		#default
+
+		#[doc = #builder_doc]
+		#[derive(Default)]
+		#vis struct #builder {
+			#(#names: ::std::option::Option<#types>,)*
+		}
+
+		#[allow(dead_code)]
+		impl #builder {
+			#(
+				#vis fn #names(mut self, value: impl ::std::convert::Into<#types>) -> Self {
+					self.#names = Some(value.into());
+					self
+				}
+			)*
+
+			/// Creates the template, a missing parameter without default
+			/// is returned as error.
+			#vis fn build(self) -> ::std::result::Result<#name, &'static str> {
+				Ok(#name { #(#names: #values,)* })
+			}
+		}
+
+		impl ::std::fmt::Display for #name {
+			fn fmt(&self, #fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+				#display
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn collect_source(
+		source: &str,
+	) -> Result<Vec<Parameter>, (usize, String)>
+	{
+		let parts =
+			crate::parse_all(&mut crate::TemplateInfo::default(), source)
+				.unwrap();
+		collect(&parts)
+	}
+
+	#[test]
+	fn parameters() {
+		let parameters = collect_source(
+			"<#@ parameter name=\"a\" type=\"Vec<u8>\" #>\n<#@ parameter \
+			 name=\"b\" type=\"u8\" default=\"1\" #>",
+		)
+		.unwrap();
+		assert_eq!(parameters.len(), 2);
+		assert_eq!(parameters[0].name, "a");
+		assert!(parameters[0].default.is_none());
+		assert!(parameters[1].default.is_some());
+
+		assert_eq!(
+			collect_source(
+				"<#@ parameter name=\"a\" type=\"u8\" #>\n<#@ parameter \
+				 name=\"a\" type=\"u8\" #>"
+			)
+			.err()
+			.unwrap(),
+			(2, "The parameter a is declared twice".into())
+		);
+		// The directive schema requires the type
+		let mut info = crate::TemplateInfo::default();
+		let source = "text\n\n<#@ parameter name=\"a\" #>";
+		assert_eq!(
+			crate::parse_all(&mut info, source).unwrap_err().reason,
+			"The `parameter` directive needs the parameter `type`"
+		);
+	}
+
+	#[test]
+	fn struct_names() {
+		let name = |path| type_name(Path::new(path)).map(|i| i.to_string());
+		assert_eq!(name("templates/report.html.tt").unwrap(), "Report");
+		assert_eq!(name("sign_up.tt").unwrap(), "SignUp");
+		assert_eq!(name("order-shipped.txt.tt").unwrap(), "OrderShipped");
+		assert_eq!(name("1.tt"), None);
+	}
+}
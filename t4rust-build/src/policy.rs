@@ -0,0 +1,190 @@
+//! Restricts the Rust code which is allowed in the code blocks of a template.
+//!
+//! A restricted template can only contain text, expression blocks and the
+//! control flow constructs which are explicitly allowed, e.g.
+//! `#[template(path = "...", restricted, allow = "if, for")]`.
+
+use crate::escape;
+use crate::lexer::{self, Token};
+use crate::TemplateError;
+
+/// A control flow construct which can be allowed in restricted templates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Construct {
+	/// `if cond {`, `} else if cond {`, `} else {` and `}`
+	If,
+	/// `for pat in expr {` and `}`
+	For,
+}
+
+impl Construct {
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"if" => Some(Construct::If),
+			"for" => Some(Construct::For),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CodePolicy {
+	pub restricted: bool,
+	pub allowed: Vec<Construct>,
+}
+
+impl CodePolicy {
+	/// Checks a code block, `offset` is the position of `code` in the
+	/// template.
+	pub fn check(
+		&self,
+		code: &str,
+		offset: usize,
+	) -> Result<(), TemplateError>
+	{
+		if !self.restricted {
+			return Ok(());
+		}
+
+		let tokens = lexer::tokenize(code);
+		let start = match tokens.first() {
+			Some((start, _)) => *start,
+			// Only comments
+			None => return Ok(()),
+		};
+		if self.is_allowed(code, &tokens) {
+			return Ok(());
+		}
+
+		let reason = if self.allowed.is_empty() {
+			"Code blocks are not allowed in restricted templates, use \
+			 expression blocks instead"
+				.to_string()
+		} else {
+			let allowed: Vec<_> = self
+				.allowed
+				.iter()
+				.map(|c| match c {
+					Construct::If => "`if cond {`, `} else {`",
+					Construct::For => "`for x in iter {`",
+				})
+				.collect();
+			format!(
+				"Only {} and `}}` are allowed in code blocks of restricted \
+				 templates",
+				allowed.join(", ")
+			)
+		};
+		Err(TemplateError { index: offset + start, reason })
+	}
+
+	/// Checks an expression block, `offset` is the position of `expr` in the
+	/// template.
+	pub fn check_expression(
+		&self,
+		expr: &str,
+		offset: usize,
+	) -> Result<(), TemplateError>
+	{
+		let inner = escape::strip_raw_marker(expr).unwrap_or(expr);
+		if !self.restricted || is_simple(inner) {
+			return Ok(());
+		}
+		Err(TemplateError {
+			index: offset,
+			reason: "Only fields, literals, operators and method calls are \
+			         allowed in expressions of restricted templates"
+				.into(),
+		})
+	}
+
+	fn is_allowed(&self, code: &str, tokens: &[(usize, Token)]) -> bool {
+		if self.allowed.is_empty() {
+			return false;
+		}
+
+		// The source between the tokens `from` and the last token
+		let header =
+			|from: usize| &code[tokens[from].0..tokens.last().unwrap().0];
+		let kinds: Vec<_> = tokens.iter().map(|(_, t)| t).collect();
+		let (closing, rest) = match kinds.as_slice() {
+			[Token::Punct('}'), rest @ ..] => (true, rest),
+			rest => (false, rest),
+		};
+		let first = usize::from(closing);
+		match rest {
+			[] => closing,
+			[Token::Ident("else"), Token::Punct('{')] => {
+				closing && self.allowed.contains(&Construct::If)
+			}
+			[
+				Token::Ident("else"),
+				Token::Ident("if"),
+				_,
+				..,
+				Token::Punct('{'),
+			] => {
+				closing
+					&& self.allowed.contains(&Construct::If)
+					&& is_condition(header(first + 2))
+			}
+			[Token::Ident("if"), _, .., Token::Punct('{')] => {
+				!closing
+					&& self.allowed.contains(&Construct::If)
+					&& is_condition(header(first + 1))
+			}
+			[Token::Ident("for"), header_tokens @ .., Token::Punct('{')] => {
+				let in_index = header_tokens
+					.iter()
+					.position(|t| **t == Token::Ident("in"));
+				match in_index {
+					Some(i) if !closing && i + 2 < rest.len() => {
+						self.allowed.contains(&Construct::For)
+							&& is_simple(header(first + i + 2))
+					}
+					_ => false,
+				}
+			}
+			_ => false,
+		}
+	}
+}
+
+/// A condition is a simple expression or `let pat = expr` with a simple
+/// expression.
+fn is_condition(code: &str) -> bool {
+	match syn::parse_str::<syn::Expr>(code) {
+		Ok(syn::Expr::Let(expr)) => is_simple_expr(&expr.expr),
+		Ok(expr) => is_simple_expr(&expr),
+		Err(_) => false,
+	}
+}
+
+/// Simple expressions only read values and call methods on them.
+///
+/// Blocks, closures, function calls and macros could run arbitrary code.
+fn is_simple(code: &str) -> bool {
+	syn::parse_str::<syn::Expr>(code).is_ok_and(|expr| is_simple_expr(&expr))
+}
+
+fn is_simple_expr(expr: &syn::Expr) -> bool {
+	use syn::Expr;
+
+	match expr {
+		Expr::Lit(_) | Expr::Path(_) => true,
+		Expr::Field(e) => is_simple_expr(&e.base),
+		Expr::MethodCall(e) => {
+			is_simple_expr(&e.receiver) && e.args.iter().all(is_simple_expr)
+		}
+		Expr::Binary(e) => is_simple_expr(&e.left) && is_simple_expr(&e.right),
+		Expr::Unary(e) => is_simple_expr(&e.expr),
+		Expr::Reference(e) => is_simple_expr(&e.expr),
+		Expr::Paren(e) => is_simple_expr(&e.expr),
+		Expr::Group(e) => is_simple_expr(&e.expr),
+		Expr::Index(e) => is_simple_expr(&e.expr) && is_simple_expr(&e.index),
+		Expr::Range(e) => e.from.iter().chain(&e.to).all(|e| is_simple_expr(e)),
+		Expr::Tuple(e) => e.elems.iter().all(is_simple_expr),
+		Expr::Array(e) => e.elems.iter().all(is_simple_expr),
+		_ => false,
+	}
+}
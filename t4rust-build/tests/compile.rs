@@ -0,0 +1,142 @@
+use std::path::PathBuf;
+
+fn out_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir()
+		.join(format!("t4rust-build-{}-{}", name, std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+#[test]
+fn compile_with_parameters() {
+	let dir = out_dir("parameters");
+	let path = t4rust_build::compile("tests/report.tt", &dir).unwrap();
+	assert_eq!(path, dir.join("report.rs"));
+
+	let code = std::fs::read_to_string(&path).unwrap();
+	std::fs::remove_dir_all(&dir).unwrap();
+	assert!(code.contains("pub struct Report {\n    pub title: String,\n}"));
+	assert!(code.contains("pub struct ReportBuilder"));
+	assert!(code.contains("impl ::std::fmt::Display for Report"));
+	syn::parse_file(&code).unwrap();
+}
+
+#[test]
+fn compile_display_only() {
+	let dir = out_dir("display");
+	let path =
+		t4rust_build::compile_for("tests/plain.html.tt", "Page", &dir).unwrap();
+	assert_eq!(path, dir.join("plain.html.rs"));
+
+	let code = std::fs::read_to_string(&path).unwrap();
+	std::fs::remove_dir_all(&dir).unwrap();
+	assert!(code.starts_with("impl ::std::fmt::Display for Page {"));
+	// The built-in html escaper is inferred from the file name
+	assert!(code.contains("fn __t4rust_escape_html"));
+}
+
+#[test]
+fn template_error() {
+	let dir = out_dir("error");
+	let template = std::env::temp_dir().join(format!(
+		"t4rust-build-broken-{}.tt",
+		std::process::id()
+	));
+	std::fs::write(&template, "Items:\n<# for i in 0..3 { #>\n").unwrap();
+	let err = t4rust_build::compile(&template, &dir).unwrap_err();
+	std::fs::remove_file(&template).unwrap();
+	std::fs::remove_dir_all(&dir).unwrap();
+	assert!(err.to_string().ends_with(
+		".tt:2: Unclosed '{' opened in this code block"
+	));
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_path() {
+	use std::ffi::OsStr;
+	use std::os::unix::ffi::OsStrExt;
+
+	let dir = out_dir("non-utf8");
+	let template = dir.join(OsStr::from_bytes(b"page-\xff.tt"));
+	std::fs::write(&template, "Hello").unwrap();
+	let err = t4rust_build::compile_for(&template, "Page", &dir).unwrap_err();
+	std::fs::remove_dir_all(&dir).unwrap();
+	assert!(err
+		.to_string()
+		.ends_with(": The template path is not valid UTF-8"));
+}
+
+#[test]
+fn parameter_error() {
+	let dir = out_dir("parameter-error");
+	let template = std::env::temp_dir()
+		.join(format!("t4rust-build-parameter-{}.tt", std::process::id()));
+	std::fs::write(
+		&template,
+		"Report\n\n<#@ parameter name=\"a b\" type=\"u8\" #>\n",
+	)
+	.unwrap();
+	let err = t4rust_build::compile(&template, &dir).unwrap_err();
+	std::fs::remove_file(&template).unwrap();
+	std::fs::remove_dir_all(&dir).unwrap();
+	assert!(err
+		.to_string()
+		.ends_with(".tt:3: Expected an identifier for `name`"));
+}
+
+#[test]
+fn expand_with_line_comments() {
+	let template = std::env::temp_dir()
+		.join(format!("t4rust-build-expand-{}.tt", std::process::id()));
+	std::fs::write(
+		&template,
+		"<#@ template cleanws=\"true\" #>\n<# for i in 0..3 { #>\n  \
+		 <#= i #>\n<# } #>\nDone\n",
+	)
+	.unwrap();
+	let config = t4rust_build::config::CrateConfig::default();
+	let code = t4rust_build::expand(&config, &template, Some("List"));
+	std::fs::remove_file(&template).unwrap();
+	let code = code.unwrap();
+	let start = code.find("// line").unwrap();
+	let end = code.find("Ok(())").unwrap() + "Ok(())".len();
+	assert_eq!(
+		&code[start..end],
+		"// line 2: <# for i in 0..3 { #>
+                    for i in 0..3 {
+                        // line 3: <#= i #>
+                        _fmt.write_str(r#\"  \"#)?;
+                        write!(_fmt, \"{}\", i)?;
+                        _fmt.write_str(r#\"
+\"#)?;
+                        // line 4: <# } #>
+                    }
+                    // line 5: Done
+                    _fmt.write_str(r#\"Done
+\"#)?;
+                    Ok(())"
+	);
+	assert!(code.starts_with("impl ::std::fmt::Display for List {"));
+}
+
+#[test]
+fn syntax_error() {
+	let dir = out_dir("syntax");
+	let template = std::env::temp_dir()
+		.join(format!("t4rust-build-syntax-{}.tt", std::process::id()));
+	std::fs::write(&template, "Items:\n<# let x = 1 +; #>\n<#= x #>\n")
+		.unwrap();
+	let config = t4rust_build::config::CrateConfig::default();
+	let expanded = t4rust_build::expand(&config, &template, Some("List"));
+	let compiled = t4rust_build::compile(&template, &dir);
+	std::fs::remove_file(&template).unwrap();
+	std::fs::remove_dir_all(&dir).unwrap();
+	for err in &[expanded.unwrap_err(), compiled.unwrap_err()] {
+		assert!(
+			err.to_string().ends_with(".tt:2: expected expression"),
+			"{}",
+			err
+		);
+	}
+}
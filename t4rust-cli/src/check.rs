@@ -0,0 +1,168 @@
+//! `t4rust check`, checks all templates of a project without compiling it.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use crate::project;
+
+pub const USAGE: &str = "\
+Usage: t4rust check [--format <FORMAT>] [DIR]
+
+Checks the templates in DIR (the current directory by default): all .tt files
+and the templates the attributes in the src directory of each crate refer to.
+
+Options:
+    --format <FORMAT>  `human` (default) or `json` for one JSON object per
+                       problem and line";
+
+/// The parsed command line.
+enum Args {
+	Check {
+		format: Format,
+		dir: PathBuf,
+	},
+	/// `-h` or `--help`, prints the usage.
+	Help,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+	Human,
+	Json,
+}
+
+/// A problem in a file of the project.
+#[derive(PartialEq)]
+struct Report {
+	file: PathBuf,
+	line: usize,
+	column: Option<usize>,
+	message: String,
+}
+
+pub fn run(args: impl Iterator<Item = String>) -> ExitCode {
+	let (format, dir) = match parse_args(args) {
+		Ok(Args::Check { format, dir }) => (format, dir),
+		Ok(Args::Help) => {
+			println!("{}", USAGE);
+			return ExitCode::SUCCESS;
+		}
+		Err(err) => {
+			eprintln!("{}\n\n{}", err, USAGE);
+			return ExitCode::from(2);
+		}
+	};
+
+	let templates = match project::find_templates(&dir) {
+		Ok(templates) => templates,
+		Err(err) => {
+			eprintln!("{}: {}", dir.display(), err);
+			return ExitCode::FAILURE;
+		}
+	};
+
+	let mut reports = Vec::new();
+	for template in &templates {
+		for report in check(template) {
+			// Templates which are referenced twice are only reported once
+			if !reports.contains(&report) {
+				print(format, &report);
+				reports.push(report);
+			}
+		}
+	}
+
+	if format == Format::Human {
+		println!(
+			"Checked {} templates, found {} problems",
+			templates.len(),
+			reports.len()
+		);
+	}
+	if reports.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+	let mut format = Format::Human;
+	let mut dir = None;
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--format" => {
+				format = match args.next().as_deref() {
+					Some("human") => Format::Human,
+					Some("json") => Format::Json,
+					_ => {
+						return Err("Expected `human` or `json` after \
+						            --format"
+							.into());
+					}
+				}
+			}
+			"-h" | "--help" => return Ok(Args::Help),
+			_ if arg.starts_with('-') => {
+				return Err(format!("Unknown option {}", arg));
+			}
+			_ if dir.is_none() => dir = Some(arg.into()),
+			_ => return Err("Only one directory can be checked".into()),
+		}
+	}
+	Ok(Args::Check { format, dir: dir.unwrap_or_else(|| ".".into()) })
+}
+
+fn check(template: &project::Template) -> Vec<Report> {
+	let source = match std::fs::read_to_string(&template.path) {
+		Ok(source) => source,
+		Err(err) => {
+			let (file, line) = match &template.origin {
+				Some((file, line)) => (file.clone(), *line),
+				None => (template.path.clone(), 1),
+			};
+			return vec![Report {
+				file,
+				line,
+				column: None,
+				message: format!(
+					"Could not read the template {}: {}",
+					display(&template.path),
+					err
+				),
+			}];
+		}
+	};
+
+	t4rust_build::check::check(&template.info, &source)
+		.into_iter()
+		.map(|problem| Report {
+			file: template.path.clone(),
+			line: problem.line,
+			column: Some(problem.column),
+			message: problem.message,
+		})
+		.collect()
+}
+
+fn print(format: Format, report: &Report) {
+	let file = display(&report.file);
+	match format {
+		Format::Human => {
+			let column =
+				report.column.map(|c| format!(":{}", c)).unwrap_or_default();
+			println!("error: {}", report.message);
+			println!("  --> {}:{}{}\n", file, report.line, column);
+		}
+		Format::Json => {
+			let report = serde_json::json!({
+				"file": file,
+				"line": report.line,
+				"column": report.column,
+				"message": report.message,
+			});
+			println!("{}", report);
+		}
+	}
+}
+
+/// Paths are shown relative to the current directory without `./`.
+fn display(path: &Path) -> String {
+	path.strip_prefix(".").unwrap_or(path).display().to_string()
+}
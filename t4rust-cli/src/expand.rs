@@ -0,0 +1,94 @@
+//! `t4rust expand`, prints the code generated for a template.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use t4rust_build::config::CrateConfig;
+
+pub const USAGE: &str = "\
+Usage: t4rust expand [--name <NAME>] <TEMPLATE>
+
+Prints the code generated for TEMPLATE with the settings of the crate that
+contains it. A comment before the statements of each template line shows the
+line they were generated from.
+
+Options:
+    --name <NAME>  The name of the struct, by default it is named after the
+                   file";
+
+/// The parsed command line.
+enum Args {
+	Expand {
+		name: Option<String>,
+		template: PathBuf,
+	},
+	/// `-h` or `--help`, prints the usage.
+	Help,
+}
+
+pub fn run(args: impl Iterator<Item = String>) -> ExitCode {
+	let (name, template) = match parse_args(args) {
+		Ok(Args::Expand { name, template }) => (name, template),
+		Ok(Args::Help) => {
+			println!("{}", USAGE);
+			return ExitCode::SUCCESS;
+		}
+		Err(err) => {
+			eprintln!("{}\n\n{}", err, USAGE);
+			return ExitCode::from(2);
+		}
+	};
+
+	let path = match template.canonicalize() {
+		Ok(path) => path,
+		Err(err) => {
+			eprintln!("{}: {}", template.display(), err);
+			return ExitCode::FAILURE;
+		}
+	};
+	let code = crate_config(&path).and_then(|config| {
+		t4rust_build::expand(&config, &path, name.as_deref())
+	});
+	match code {
+		Ok(code) => {
+			print!("{}", code);
+			ExitCode::SUCCESS
+		}
+		Err(err) => {
+			eprintln!("error: {}", err);
+			ExitCode::FAILURE
+		}
+	}
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+	let mut name = None;
+	let mut template = None;
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--name" => match args.next() {
+				Some(value) => name = Some(value),
+				None => {
+					return Err("Expected a struct name after --name".into());
+				}
+			},
+			"-h" | "--help" => return Ok(Args::Help),
+			_ if arg.starts_with('-') => {
+				return Err(format!("Unknown option {}", arg));
+			}
+			_ if template.is_none() => template = Some(arg.into()),
+			_ => return Err("Only one template can be expanded".into()),
+		}
+	}
+	match template {
+		Some(template) => Ok(Args::Expand { name, template }),
+		None => Err("Missing the template".into()),
+	}
+}
+
+/// The configuration of the crate which contains `file`.
+fn crate_config(file: &Path) -> Result<CrateConfig, t4rust_build::Error> {
+	file.ancestors()
+		.find(|dir| dir.join("Cargo.toml").is_file())
+		.map_or_else(|| Ok(CrateConfig::default()), CrateConfig::load)
+}
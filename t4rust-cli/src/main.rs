@@ -0,0 +1,36 @@
+//! `t4rust`, command line tools for t4rust templates.
+
+mod check;
+mod expand;
+mod project;
+
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: t4rust <COMMAND> [OPTIONS]
+
+Commands:
+    check   Checks all templates of a project without compiling it
+    expand  Prints the generated code of a template
+
+See `t4rust <COMMAND> --help` for the options of a command.";
+
+fn main() -> ExitCode {
+	let mut args = std::env::args().skip(1);
+	match args.next().as_deref() {
+		Some("check") => check::run(args),
+		Some("expand") => expand::run(args),
+		Some("-h" | "--help") => {
+			println!("{}", USAGE);
+			ExitCode::SUCCESS
+		}
+		Some(command) => {
+			eprintln!("Unknown command {}\n\n{}", command, USAGE);
+			ExitCode::from(2)
+		}
+		None => {
+			eprintln!("{}", USAGE);
+			ExitCode::from(2)
+		}
+	}
+}
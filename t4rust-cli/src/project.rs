@@ -0,0 +1,225 @@
+//! Finds the templates of a project.
+//!
+//! These are the templates the `#[template(path = "...")]` and
+//! `#[TemplatePath = "..."]` attributes in the `src` directory of each crate
+//! refer to, compiled with the options of the attribute, and all other `.tt`
+//! files, compiled with the crate defaults.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use syn::{Attribute, Item, LitStr};
+use t4rust_build::attributes::TemplateAttributes;
+use t4rust_build::config::CrateConfig;
+use t4rust_build::TemplateInfo;
+
+const TEMPLATE_EXTENSION: &str = "tt";
+/// Directories which never contain templates of the project.
+const SKIPPED_DIRECTORIES: &[&str] = &["target", "node_modules"];
+
+/// A template and the settings it is compiled with.
+#[derive(Debug)]
+pub struct Template {
+	/// The template file, it might not exist if an attribute refers to it.
+	pub path: PathBuf,
+	pub info: TemplateInfo,
+	/// The source file and line of the attribute which refers to the
+	/// template.
+	pub origin: Option<(PathBuf, usize)>,
+}
+
+#[derive(Default)]
+struct Finder {
+	templates: Vec<Template>,
+	/// The canonical paths of the templates which attributes refer to.
+	referenced: HashSet<PathBuf>,
+}
+
+/// Finds all templates in `dir`, sorted by the crates and paths where they
+/// were found.
+pub fn find_templates(dir: &Path) -> std::io::Result<Vec<Template>> {
+	let mut finder = Finder::default();
+	let config = CrateConfig { root: dir.to_path_buf(), ..Default::default() };
+	finder.walk(dir, &config)?;
+	Ok(finder.templates)
+}
+
+impl Finder {
+	fn walk(
+		&mut self,
+		dir: &Path,
+		config: &CrateConfig,
+	) -> std::io::Result<()>
+	{
+		// Every crate has its own configuration
+		let own;
+		let config = if dir.join("Cargo.toml").is_file() {
+			own = CrateConfig::load(dir).map_err(|err| {
+				std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+			})?;
+			self.scan_sources(&dir.join("src"), &own)?;
+			&own
+		} else {
+			config
+		};
+
+		let mut entries = std::fs::read_dir(dir)?
+			.map(|entry| entry.map(|e| e.path()))
+			.collect::<std::io::Result<Vec<_>>>()?;
+		entries.sort();
+		for path in entries {
+			let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+			if path.is_dir() {
+				if !name.starts_with('.')
+					&& !SKIPPED_DIRECTORIES.contains(&name)
+				{
+					self.walk(&path, config)?;
+				}
+			} else if has_extension(&path, TEMPLATE_EXTENSION)
+				&& !path
+					.canonicalize()
+					.is_ok_and(|path| self.referenced.contains(&path))
+			{
+				self.templates.push(Template {
+					info: info_for(config, config.info(), None, &path),
+					path,
+					origin: None,
+				});
+			}
+		}
+		Ok(())
+	}
+
+	/// Collects the templates of the attributes in all Rust files in `dir`.
+	fn scan_sources(
+		&mut self,
+		dir: &Path,
+		config: &CrateConfig,
+	) -> std::io::Result<()>
+	{
+		if !dir.is_dir() {
+			return Ok(());
+		}
+		let mut entries = std::fs::read_dir(dir)?
+			.map(|entry| entry.map(|e| e.path()))
+			.collect::<std::io::Result<Vec<_>>>()?;
+		entries.sort();
+		for path in entries {
+			if path.is_dir() {
+				self.scan_sources(&path, config)?;
+			} else if has_extension(&path, "rs") {
+				let source = std::fs::read_to_string(&path)?;
+				// The compiler reports files which do not parse
+				if let Ok(file) = syn::parse_file(&source) {
+					self.scan_items(&path, &file.items, config);
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn scan_items(
+		&mut self,
+		file: &Path,
+		items: &[Item],
+		config: &CrateConfig,
+	)
+	{
+		for item in items {
+			match item {
+				Item::Struct(item) => {
+					self.scan_attrs(file, &item.attrs, None, config);
+				}
+				Item::Enum(item) => {
+					// Variants inherit the options of the enum
+					let parent = match self.scan_attrs(
+						file,
+						&item.attrs,
+						None,
+						config,
+					) {
+						Some(parent) => parent,
+						None => continue,
+					};
+					for variant in &item.variants {
+						let attrs = &variant.attrs;
+						self.scan_attrs(file, attrs, Some(&parent), config);
+					}
+				}
+				Item::Mod(item) => {
+					if let Some((_, items)) = &item.content {
+						self.scan_items(file, items, config);
+					}
+				}
+				_ => {}
+			}
+		}
+	}
+
+	/// Adds the templates of the attributes of one item and returns the
+	/// options of its unnamed template.
+	///
+	/// Items with invalid attributes are skipped, the compiler reports them.
+	fn scan_attrs(
+		&mut self,
+		file: &Path,
+		attrs: &[Attribute],
+		parent: Option<&TemplateAttributes>,
+		config: &CrateConfig,
+	) -> Option<TemplateAttributes>
+	{
+		let attributes = match parent {
+			Some(parent) => parent.inherit(attrs),
+			None => TemplateAttributes::parse(attrs, config.info()),
+		}
+		.ok()?;
+		let named = attributes.named(attrs).ok()?;
+
+		for attributes in Some(&attributes).into_iter().chain(&named) {
+			if let Some(path) = &attributes.path {
+				self.reference(file, path, attributes, config);
+			}
+		}
+		Some(attributes)
+	}
+
+	fn reference(
+		&mut self,
+		file: &Path,
+		path: &LitStr,
+		attributes: &TemplateAttributes,
+		config: &CrateConfig,
+	)
+	{
+		let line = path.span().start().line;
+		let path = config.root.join(path.value());
+		if let Ok(path) = path.canonicalize() {
+			self.referenced.insert(path);
+		}
+		let info = attributes.info.clone();
+		self.templates.push(Template {
+			info: info_for(config, info, attributes.escape.as_deref(), &path),
+			path,
+			origin: Some((file.to_path_buf(), line)),
+		});
+	}
+}
+
+/// The initial settings of a template file, `escape` overrides the escape
+/// function of the crate.
+fn info_for(
+	config: &CrateConfig,
+	mut info: TemplateInfo,
+	escape: Option<&str>,
+	path: &Path,
+) -> TemplateInfo
+{
+	if let Some(escape) = escape.or_else(|| config.escape_for(path)) {
+		info.print_postprocessor = escape.to_string();
+	}
+	info
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+	path.extension().and_then(|e| e.to_str()) == Some(extension)
+}
@@ -0,0 +1,126 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+/// A crate with broken templates in a temporary directory.
+struct Project(PathBuf);
+
+impl Project {
+	fn new(name: &str, files: &[(&str, &str)]) -> Self {
+		let dir = std::env::temp_dir().join(format!(
+			"t4rust-check-{}-{}",
+			name,
+			std::process::id()
+		));
+		for (path, content) in files {
+			let path = dir.join(path);
+			std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+			std::fs::write(path, content).unwrap();
+		}
+		Project(dir)
+	}
+
+	/// Runs `t4rust check` in the project, returns the exit code and stdout.
+	fn check(&self, args: &[&str]) -> (i32, String) {
+		let output = Command::new(env!("CARGO_BIN_EXE_t4rust"))
+			.arg("check")
+			.args(args)
+			.current_dir(&self.0)
+			.output()
+			.unwrap();
+		let stdout = String::from_utf8(output.stdout).unwrap();
+		(output.status.code().unwrap(), stdout)
+	}
+}
+
+impl Drop for Project {
+	fn drop(&mut self) { std::fs::remove_dir_all(&self.0).unwrap(); }
+}
+
+const MANIFEST: &str = r#"
+[package]
+name = "site"
+version = "0.1.0"
+
+[package.metadata.t4rust]
+root = "templates"
+"#;
+
+const LIB: &str = r#"
+#[derive(Template)]
+#[template(path = "page.html.tt", restricted)]
+struct Page;
+
+mod mail {
+	#[derive(Template)]
+	#[TemplatePath = "missing.tt"]
+	struct Missing;
+}
+"#;
+
+#[test]
+fn json() {
+	let project = Project::new(
+		"json",
+		&[
+			("Cargo.toml", MANIFEST),
+			("src/lib.rs", LIB),
+			("templates/page.html.tt", "<h1>\n<# let title = 1; #>\n"),
+			("templates/broken.tt", "a\n<#= b\n"),
+			("templates/directive.tt", "<#@ template strict=\"yes\" #>"),
+			(
+				"templates/syntax.tt",
+				"<# for i in 0..3 { #>\n<# let x = ; #>\n<# } #>",
+			),
+			("templates/ok.tt", "<# for i in 0..3 { #>\n<#= i #>\n<# } #>"),
+			("target/ignored.tt", "<#"),
+		],
+	);
+
+	let (code, stdout) = project.check(&["--format", "json"]);
+	assert_eq!(code, 1);
+	let lines: Vec<_> = stdout.lines().collect();
+	assert_eq!(
+		lines,
+		[
+			r#"{"column":4,"file":"templates/page.html.tt","line":2,"message":"Code blocks are not allowed in restricted templates, use expression blocks instead"}"#,
+			r#"{"column":null,"file":"src/lib.rs","line":8,"message":"Could not read the template templates/missing.tt: No such file or directory (os error 2)"}"#,
+			r#"{"column":1,"file":"templates/broken.tt","line":2,"message":"Unclosed code or expression block"}"#,
+			r#"{"column":22,"file":"templates/directive.tt","line":1,"message":"Expected `true` or `false` for `strict`"}"#,
+			r#"{"column":12,"file":"templates/syntax.tt","line":2,"message":"expected expression"}"#,
+		]
+	);
+}
+
+#[test]
+fn human() {
+	let project = Project::new(
+		"human",
+		&[
+			("Cargo.toml", MANIFEST),
+			("templates/ok.tt", "Hello <#= name #>!"),
+			("templates/nested/broken.tt", "<# if a { #>"),
+		],
+	);
+
+	let (code, stdout) = project.check(&[]);
+	assert_eq!(code, 1);
+	assert_eq!(
+		stdout,
+		"error: Unclosed '{' opened in this code block\n  --> \
+		 templates/nested/broken.tt:1:9\n\nChecked 2 templates, found 1 \
+		 problems\n"
+	);
+
+	std::fs::remove_file(project.0.join("templates/nested/broken.tt")).unwrap();
+	let (code, stdout) = project.check(&[]);
+	assert_eq!(code, 0);
+	assert_eq!(stdout, "Checked 1 templates, found 0 problems\n");
+}
+
+#[test]
+fn help() {
+	let project = Project::new("help", &[("Cargo.toml", MANIFEST)]);
+	let (code, stdout) = project.check(&["--help"]);
+	assert_eq!(code, 0);
+	assert!(stdout.starts_with("Usage: t4rust check"));
+}
@@ -0,0 +1,56 @@
+use std::process::Command;
+
+#[test]
+fn expand() {
+	let dir = std::env::temp_dir()
+		.join(format!("t4rust-expand-{}", std::process::id()));
+	std::fs::create_dir_all(dir.join("templates")).unwrap();
+	std::fs::write(
+		dir.join("Cargo.toml"),
+		"[package]\nname = \"site\"\n\n[package.metadata.t4rust]\nescape = \
+		 \"html\"\n",
+	)
+	.unwrap();
+	std::fs::write(dir.join("templates/item_list.tt"), "a\n<#= b #>\n")
+		.unwrap();
+
+	let expand = |args: &[&str]| {
+		let output = Command::new(env!("CARGO_BIN_EXE_t4rust"))
+			.arg("expand")
+			.args(args)
+			.current_dir(&dir)
+			.output()
+			.unwrap();
+		let stdout = String::from_utf8(output.stdout).unwrap();
+		let stderr = String::from_utf8(output.stderr).unwrap();
+		(output.status.code().unwrap(), stdout, stderr)
+	};
+
+	let (code, stdout, _) = expand(&["templates/item_list.tt"]);
+	assert_eq!(code, 0);
+	assert!(stdout.starts_with("impl ::std::fmt::Display for ItemList {"));
+	// The crate configures the escape function
+	assert!(stdout.contains("fn __t4rust_escape_html"));
+	assert!(stdout.contains(
+		"                    // line 2: <#= b #>\n                    {\n"
+	));
+
+	let (code, stdout, _) =
+		expand(&["--name", "Items", "templates/item_list.tt"]);
+	assert_eq!(code, 0);
+	assert!(stdout.starts_with("impl ::std::fmt::Display for Items {"));
+
+	let (code, _, stderr) =
+		expand(&["--name", "no name", "templates/item_list.tt"]);
+	assert_eq!(code, 1);
+	assert!(stderr.ends_with(
+		"templates/item_list.tt:1: The struct name \"no name\" is no \
+		 identifier\n"
+	));
+
+	let (code, stdout, _) = expand(&["--help"]);
+	assert_eq!(code, 0);
+	assert!(stdout.starts_with("Usage: t4rust expand"));
+
+	std::fs::remove_dir_all(&dir).unwrap();
+}
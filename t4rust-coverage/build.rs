@@ -0,0 +1,5 @@
+fn main() {
+	// The templates of the tests are instrumented
+	println!("cargo:rustc-check-cfg=cfg(t4rust_coverage)");
+	println!("cargo:rustc-cfg=t4rust_coverage");
+}
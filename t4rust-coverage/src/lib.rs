@@ -0,0 +1,169 @@
+//! Coverage reports for [t4rust](https://github.com/ReSpeak/t4rust) templates.
+//!
+//! With `coverage` enabled for a crate, the generated code of every template
+//! of the crate records which of its text and code blocks are rendered in a
+//! process-global registry. After the tests rendered the templates, the
+//! registry can be written as lcov file or HTML report:
+//! ```toml
+//! [target.'cfg(t4rust_coverage)'.dependencies]
+//! t4rust-coverage = "0.3"
+//!
+//! [package.metadata.t4rust]
+//! coverage = true
+//! ```
+//!
+//! The instrumentation is only compiled with `--cfg t4rust_coverage`, e.g.
+//! `RUSTFLAGS="--cfg t4rust_coverage" cargo test`.
+//!
+//! ```no_run
+//! std::fs::write("target/templates.lcov", t4rust_coverage::lcov()).unwrap();
+//! std::fs::write("target/templates.html", t4rust_coverage::html()).unwrap();
+//! ```
+//!
+//! Blocks are keyed by their template file and first line. Only templates
+//! which were rendered at least once are part of the reports.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, MutexGuard};
+
+/// A text or code block of a template.
+///
+/// The blocks which start on the same line share one entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+	/// The first line, 1-based.
+	pub start: u32,
+	/// The last line, inclusive.
+	pub end: u32,
+	/// How often the blocks were rendered.
+	pub hits: u64,
+}
+
+type Registry = BTreeMap<&'static str, Vec<Block>>;
+
+static REGISTRY: Mutex<Registry> = Mutex::new(BTreeMap::new());
+
+fn registry() -> MutexGuard<'static, Registry> {
+	// The registry stays consistent even if a thread panicked while holding it
+	REGISTRY.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Adds the blocks of a template, called by the generated code before its
+/// first render.
+#[doc(hidden)]
+pub fn register(file: &'static str, blocks: &[(u32, u32)]) {
+	let mut registry = registry();
+	let known = registry.entry(file).or_default();
+	for &(start, end) in blocks {
+		// Templates can be compiled for multiple structs
+		if let Err(i) = known.binary_search_by_key(&start, |b| b.start) {
+			known.insert(i, Block { start, end, hits: 0 });
+		}
+	}
+}
+
+/// Counts a render of the block starting on `line`, called by the generated
+/// code.
+#[doc(hidden)]
+pub fn hit(file: &'static str, line: u32) {
+	let mut registry = registry();
+	let blocks = registry.entry(file).or_default();
+	if let Ok(i) = blocks.binary_search_by_key(&line, |b| b.start) {
+		blocks[i].hits += 1;
+	}
+}
+
+/// The blocks of all rendered templates, sorted by file and line.
+pub fn files() -> Vec<(String, Vec<Block>)> {
+	registry()
+		.iter()
+		.map(|(file, blocks)| (file.to_string(), blocks.clone()))
+		.collect()
+}
+
+/// The hits of every line which belongs to a block.
+///
+/// A line with multiple blocks gets the lowest count, so that a partially
+/// rendered line is reported as not rendered.
+fn line_hits(blocks: &[Block]) -> BTreeMap<u32, u64> {
+	let mut lines = BTreeMap::new();
+	for block in blocks {
+		for line in block.start..=block.end {
+			let hits = lines.entry(line).or_insert(block.hits);
+			*hits = (*hits).min(block.hits);
+		}
+	}
+	lines
+}
+
+/// The coverage of all rendered templates in the lcov tracefile format.
+pub fn lcov() -> String {
+	let mut res = String::new();
+	for (file, blocks) in files() {
+		let lines = line_hits(&blocks);
+		writeln!(res, "TN:\nSF:{}", file).unwrap();
+		for (line, hits) in &lines {
+			writeln!(res, "DA:{},{}", line, hits).unwrap();
+		}
+		let hit = lines.values().filter(|hits| **hits > 0).count();
+		writeln!(res, "LF:{}\nLH:{}\nend_of_record", lines.len(), hit).unwrap();
+	}
+	res
+}
+
+/// A single HTML page which shows the source of all rendered templates with
+/// the rendered blocks in green and the blocks which were never rendered in
+/// red.
+pub fn html() -> String {
+	let mut res = String::from(
+		"<!DOCTYPE html>\n<html>\n<head>\n<meta \
+		 charset=\"utf-8\">\n<title>Template coverage</title>\n<style>\n.hit \
+		 { background: #dfd; }\n.miss { background: #fdd; \
+		 }\n</style>\n</head>\n<body>\n<h1>Template coverage</h1>\n",
+	);
+	for (file, blocks) in files() {
+		let rendered = blocks.iter().filter(|b| b.hits > 0).count();
+		writeln!(
+			res,
+			"<h2>{}</h2>\n<p>{} of {} blocks rendered</p>",
+			escape(&file),
+			rendered,
+			blocks.len()
+		)
+		.unwrap();
+
+		let source = match std::fs::read_to_string(&file) {
+			Ok(source) => source,
+			Err(err) => {
+				writeln!(res, "<p>Could not read the template: {}</p>", err)
+					.unwrap();
+				continue;
+			}
+		};
+		let lines = line_hits(&blocks);
+		res.push_str("<pre>\n");
+		for (number, line) in (1..).zip(source.lines()) {
+			let class = match lines.get(&number) {
+				Some(0) => " class=\"miss\"",
+				Some(_) => " class=\"hit\"",
+				None => "",
+			};
+			writeln!(
+				res,
+				"<span{}>{:4} {}</span>",
+				class,
+				number,
+				escape(line)
+			)
+			.unwrap();
+		}
+		res.push_str("</pre>\n");
+	}
+	res.push_str("</body>\n</html>\n");
+	res
+}
+
+fn escape(s: &str) -> String {
+	s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -0,0 +1,46 @@
+use t4rust_derive::Template;
+
+#[derive(Template)]
+#[template(path = "tests/branches.tt")]
+struct Branches {
+	admin: bool,
+	name: &'static str,
+}
+
+#[test]
+fn coverage() {
+	assert_eq!(
+		Branches { admin: false, name: "Splamy" }.to_string(),
+		"Hello Splamy\nBye\n"
+	);
+	Branches { admin: false, name: "" }.to_string();
+
+	let (file, blocks) = t4rust_coverage::files()
+		.into_iter()
+		.find(|(file, _)| file.ends_with("branches.tt"))
+		.unwrap();
+	let blocks: Vec<_> =
+		blocks.iter().map(|b| (b.start, b.end, b.hits)).collect();
+	assert_eq!(
+		blocks,
+		[(2, 2, 0), (3, 3, 0), (4, 4, 2), (5, 5, 4), (6, 6, 2), (7, 7, 2)]
+	);
+
+	let lcov = t4rust_coverage::lcov();
+	let record = "DA:2,0\nDA:3,0\nDA:4,2\nDA:5,4\nDA:6,2\nDA:7,2\nLF:6\nLH:4\n";
+	assert!(lcov.contains(&format!("SF:{}\n{}end_of_record\n", file, record)));
+
+	let html = t4rust_coverage::html();
+	assert!(html.contains("<p>4 of 6 blocks rendered</p>"));
+	assert!(
+		html.contains("<span class=\"miss\">   3 Welcome back, admin</span>")
+	);
+	assert!(html.contains(
+		"<span class=\"hit\">   5 Hello &lt;#= self.name #&gt;</span>"
+	));
+	assert!(
+		html.contains(
+			"<span>   1 &lt;#@ template cleanws=\"true\" #&gt;</span>"
+		)
+	);
+}
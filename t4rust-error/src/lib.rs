@@ -0,0 +1,117 @@
+//! Render errors of [t4rust](https://github.com/ReSpeak/t4rust) templates.
+//!
+//! A `Display` implementation can only fail with the opaque `fmt::Error`.
+//! With `errors` enabled for a crate, the generated code of its templates
+//! remembers the template file and line where rendering failed, and every
+//! template gets a `try_render()` method which returns them as
+//! [`RenderError`]:
+//! ```toml
+//! [dependencies]
+//! t4rust-error = "0.3"
+//!
+//! [package.metadata.t4rust]
+//! errors = true
+//! ```
+//!
+//! Template code can fail with its own message with `bail!`, which takes the
+//! same arguments as `format!`:
+//! ```text
+//! <# if self.price.is_none() { bail!("missing price for {}", self.name); } #>
+//! ```
+//!
+//! If a nested template fails, the error of the outer template has the error
+//! of the nested template as cause.
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+type Cause = Box<dyn Error + Send + Sync + 'static>;
+
+thread_local! {
+	/// The cause of the current failure, it is passed up as `fmt::Error`.
+	static PENDING: RefCell<Option<Cause>> = RefCell::new(None);
+}
+
+/// An error while rendering a template.
+#[derive(Debug)]
+pub struct RenderError {
+	path: Option<&'static str>,
+	line: usize,
+	cause: Cause,
+}
+
+impl RenderError {
+	/// The template file, `None` for inline templates.
+	pub fn path(&self) -> Option<&Path> { self.path.map(Path::new) }
+
+	/// The line of the block which failed, 1-based.
+	///
+	/// It is 0 if the error was not raised by a template.
+	pub fn line(&self) -> usize { self.line }
+
+	/// The underlying error, e.g. the message of `bail!`, the
+	/// [`RenderError`] of a nested template or `fmt::Error`.
+	pub fn cause(&self) -> &(dyn Error + Send + Sync + 'static) { &*self.cause }
+
+	pub fn into_cause(self) -> Box<dyn Error + Send + Sync + 'static> {
+		self.cause
+	}
+}
+
+impl fmt::Display for RenderError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.path {
+			Some(path) => write!(f, "{}:{}: {}", path, self.line, self.cause),
+			None => write!(f, "line {}: {}", self.line, self.cause),
+		}
+	}
+}
+
+impl Error for RenderError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> { Some(&*self.cause) }
+}
+
+/// Renders a template into a string.
+///
+/// This is what the generated `try_render()` methods call, it also works for
+/// inline templates:
+/// ```ignore
+/// let text = t4rust_error::render(&t4!("Dear <#= name #>,"))?;
+/// ```
+pub fn render(template: &impl fmt::Display) -> Result<String, RenderError> {
+	use std::fmt::Write;
+
+	PENDING.with(|pending| pending.borrow_mut().take());
+	let mut res = String::new();
+	match write!(res, "{}", template) {
+		Ok(()) => Ok(res),
+		Err(err) => {
+			let cause = take().unwrap_or_else(|| Box::new(err));
+			match cause.downcast::<RenderError>() {
+				Ok(err) => Err(*err),
+				Err(cause) => Err(RenderError { path: None, line: 0, cause }),
+			}
+		}
+	}
+}
+
+fn take() -> Option<Cause> {
+	PENDING.with(|pending| pending.borrow_mut().take())
+}
+
+/// Fails with a domain error, called by `bail!`.
+#[doc(hidden)]
+pub fn raise(cause: impl Into<Cause>) -> fmt::Error {
+	PENDING.with(|pending| *pending.borrow_mut() = Some(cause.into()));
+	fmt::Error
+}
+
+/// Adds the position to the current failure, called by the generated code
+/// when a template fails on `line`.
+#[doc(hidden)]
+pub fn fail(path: Option<&'static str>, line: usize) -> fmt::Error {
+	let cause = take().unwrap_or_else(|| Box::new(fmt::Error));
+	raise(RenderError { path, line, cause })
+}
@@ -0,0 +1,72 @@
+use std::fmt;
+
+use t4rust_derive::{t4, Template};
+use t4rust_error::RenderError;
+
+#[derive(Template)]
+#[template(path = "tests/item.tt", cleanws)]
+struct Item {
+	name: &'static str,
+	price: Option<u32>,
+}
+
+#[derive(Template)]
+#[template(path = "tests/order.tt", cleanws)]
+struct Order {
+	id: u32,
+	items: Vec<Item>,
+}
+
+struct Broken;
+
+impl fmt::Display for Broken {
+	fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result { Err(fmt::Error) }
+}
+
+fn file_name(err: &RenderError) -> &str {
+	err.path().unwrap().file_name().unwrap().to_str().unwrap()
+}
+
+#[test]
+fn render_ok() {
+	let order = Order { id: 7, items: Vec::new() };
+	assert_eq!(order.try_render().unwrap(), "Order 7\nEmpty\n");
+	let item = Item { name: "Tea", price: Some(3) };
+	assert_eq!(item.try_render().unwrap(), "Item: Tea\nPrice: 3\n");
+}
+
+#[test]
+fn bail_with_line() {
+	let err = Item { name: "Tea", price: None }.try_render().unwrap_err();
+	assert_eq!(file_name(&err), "item.tt");
+	assert_eq!(err.line(), 2);
+	assert_eq!(err.cause().to_string(), "missing price for Tea");
+	assert!(err.to_string().ends_with("item.tt:2: missing price for Tea"));
+}
+
+#[test]
+fn nested_template() {
+	let items = vec![
+		Item { name: "Tea", price: Some(3) },
+		Item { name: "Cake", price: None },
+	];
+	let err = Order { id: 7, items }.try_render().unwrap_err();
+	assert_eq!(file_name(&err), "order.tt");
+	assert_eq!(err.line(), 3);
+	let cause = err.cause().downcast_ref::<RenderError>().unwrap();
+	assert_eq!(file_name(cause), "item.tt");
+	assert_eq!(cause.line(), 2);
+}
+
+#[test]
+fn formatter_error() {
+	let value = Broken;
+	let err = t4rust_error::render(&t4!("a\n<#= value #>")).unwrap_err();
+	assert_eq!(err.path(), None);
+	assert_eq!(err.line(), 2);
+	assert!(err.cause().is::<fmt::Error>());
+	assert_eq!(
+		err.to_string(),
+		"line 2: an error occurred when formatting an argument"
+	);
+}
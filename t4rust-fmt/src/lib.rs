@@ -0,0 +1,251 @@
+//! The formatter of [t4rust](https://github.com/ReSpeak/t4rust) templates.
+//!
+//! [`format`] normalises the layout of a template without changing what it
+//! renders:
+//! - Single-line code and expression blocks are padded with one space,
+//!   `<#=x#>` becomes `<#= x #>`.
+//! - Directives are written on one line as `<#@ name key="value" #>`.
+//! - Code and directive blocks which are alone on their line are indented by
+//!   the nesting of the code blocks around them, if `cleanws` removes the
+//!   indentation from the output anyway. Trailing whitespace on these lines
+//!   is removed as well.
+//!
+//! Text is never touched. Both the original and the formatted template are
+//! compiled and formatting fails if the generated code differs.
+//!
+//! ```
+//! let source = "<#for i in 0..3 {#>\n<#=i#>\n<#}#>\n";
+//! let formatted = t4rust_fmt::format(source, &Default::default()).unwrap();
+//! assert_eq!(formatted, "<# for i in 0..3 { #>\n<#= i #>\n<# } #>\n");
+//! ```
+
+use std::fmt;
+use std::path::Path;
+
+use t4rust_build::lexer::{self, Token};
+use t4rust_build::TemplateInfo;
+use t4rust_parser::{
+	Directive, NodeKind, Template, BLOCK_END, CODE_START, DIRECTIVE_START,
+	EXPRESSION_START,
+};
+
+/// An error while formatting a template.
+#[derive(Debug)]
+pub enum Error {
+	/// The template does not compile, only valid templates are formatted.
+	Template(t4rust_build::Error),
+	/// The formatted template would generate different code, the template is
+	/// left as it is.
+	OutputChanged,
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Template(err) => write!(f, "{}", err),
+			Error::OutputChanged => {
+				write!(f, "Formatting would change the output of the template")
+			}
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+/// The settings of the formatter.
+#[derive(Debug, Clone)]
+pub struct Options {
+	/// The template is compiled with `cleanws` enabled, e.g. by the crate
+	/// configuration or the `#[template]` attribute. Without it only the
+	/// lines after a `cleanws` directive are reindented.
+	pub clean_whitespace: bool,
+	/// The indentation of one nesting level.
+	pub indent: String,
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Self { clean_whitespace: false, indent: "\t".into() }
+	}
+}
+
+/// Formats the source of a template.
+pub fn format(source: &str, options: &Options) -> Result<String, Error> {
+	let initial = TemplateInfo {
+		clean_whitespace: options.clean_whitespace,
+		..TemplateInfo::default()
+	};
+	let template = t4rust_parser::parse(source).map_err(|err| {
+		Error::Template(t4rust_build::Error::Template {
+			path: None,
+			line: t4rust_parser::line_col(source, err.index).0,
+			reason: err.reason,
+		})
+	})?;
+	let expected = compile(&initial, source).map_err(Error::Template)?;
+
+	let formatted = format_template(&template, options);
+	match compile(&initial, &formatted) {
+		Ok(code) if code == expected => Ok(formatted),
+		_ => Err(Error::OutputChanged),
+	}
+}
+
+/// Reads and formats a template file.
+pub fn format_file(path: &Path, options: &Options) -> Result<String, Error> {
+	let source = std::fs::read_to_string(path).map_err(|err| {
+		Error::Template(t4rust_build::Error::Io(path.to_path_buf(), err))
+	})?;
+	format(&source, options).map_err(|err| match err {
+		Error::Template(t4rust_build::Error::Template {
+			path: None,
+			line,
+			reason,
+		}) => Error::Template(t4rust_build::Error::Template {
+			path: Some(path.to_path_buf()),
+			line,
+			reason,
+		}),
+		err => err,
+	})
+}
+
+/// The generated code of a template, formatting must not change it.
+fn compile(
+	initial: &TemplateInfo,
+	source: &str,
+) -> Result<String, t4rust_build::Error>
+{
+	Ok(t4rust_build::compile_source(initial, source, None)?.tokens.to_string())
+}
+
+fn format_template(template: &Template, options: &Options) -> String {
+	let source = template.source;
+	let nodes = &template.nodes;
+
+	// The indentation depth of every block which is alone on its line and
+	// whose surrounding whitespace is removed by `cleanws`
+	let mut layout = vec![None; nodes.len()];
+	let mut clean_whitespace = options.clean_whitespace;
+	let mut depth = 0;
+	for (i, node) in nodes.iter().enumerate() {
+		let indent = match &node.kind {
+			NodeKind::Text { .. } | NodeKind::Expr(_) => continue,
+			NodeKind::Directive(dir) => {
+				// Like the compiler, a directive already applies to itself
+				if let Some(clean) = cleanws_param(dir) {
+					clean_whitespace = clean;
+				}
+				depth
+			}
+			NodeKind::Code(block) => {
+				let tokens = lexer::tokenize(&block.value);
+				let closing =
+					tokens.iter().take_while(|(_, t)| is_close(t)).count();
+				let indent = depth - closing.min(depth);
+				for (_, token) in &tokens {
+					if is_open(token) {
+						depth += 1;
+					} else if is_close(token) {
+						depth = depth.saturating_sub(1);
+					}
+				}
+				indent
+			}
+		};
+		if clean_whitespace && is_alone_on_line(source, node.span.clone()) {
+			layout[i] = Some(indent);
+		}
+	}
+
+	let mut out = String::with_capacity(source.len());
+	for (i, node) in nodes.iter().enumerate() {
+		let raw = &source[node.span.clone()];
+		let block = match &node.kind {
+			NodeKind::Text { .. } => {
+				let mut text = raw;
+				if i > 0 && layout[i - 1].is_some() {
+					text = text.trim_start_matches([' ', '\t']);
+				}
+				if layout.get(i + 1).is_some_and(Option::is_some) {
+					text = text.trim_end_matches([' ', '\t']);
+				}
+				out.push_str(text);
+				continue;
+			}
+			NodeKind::Code(block) => {
+				pad_block(CODE_START, &source[block.content.clone()])
+			}
+			NodeKind::Expr(block) => {
+				pad_block(EXPRESSION_START, &source[block.content.clone()])
+			}
+			NodeKind::Directive(dir) => Some(format_directive(source, dir)),
+		};
+		if let Some(indent) = layout[i] {
+			out.push_str(&options.indent.repeat(indent));
+		}
+		out.push_str(block.as_deref().unwrap_or(raw));
+	}
+	out
+}
+
+/// A block with its content padded by one space, `None` for empty or
+/// multi-line blocks which are kept as they are.
+fn pad_block(open: &str, content: &str) -> Option<String> {
+	let trimmed = content.trim();
+	if trimmed.is_empty() || content.contains('\n') {
+		return None;
+	}
+	Some(format!("{} {} {}", open, trimmed, BLOCK_END))
+}
+
+/// A directive on one line, `<#@ name key="value" #>`.
+fn format_directive(source: &str, dir: &Directive) -> String {
+	let mut out = format!("{} {}", DIRECTIVE_START, dir.name.value);
+	for param in &dir.params {
+		// The raw value keeps its escapes
+		let value = &source[param.value.span.clone()];
+		out.push_str(&format!(" {}=\"{}\"", param.name.value, value));
+	}
+	out.push(' ');
+	out.push_str(BLOCK_END);
+	out
+}
+
+/// The last `cleanws` setting of a `template` directive.
+fn cleanws_param(dir: &Directive) -> Option<bool> {
+	if dir.name.value != "template" {
+		return None;
+	}
+	dir.params
+		.iter()
+		.rev()
+		.filter(|p| {
+			p.name.value == "cleanws" || p.name.value == "clean_whitespace"
+		})
+		.find_map(|p| p.value.value.parse().ok())
+}
+
+/// The block at `span` is the only thing on its line except for spaces and
+/// tabs, and `cleanws` removes them.
+///
+/// Like the compiler, the line has to end with a line break and the first
+/// line of the template must not be indented.
+fn is_alone_on_line(source: &str, span: std::ops::Range<usize>) -> bool {
+	let before = &source[..span.start];
+	let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+	let indent = &before[line_start..];
+	let after = source[span.end..].trim_start_matches([' ', '\t']);
+
+	indent.chars().all(|c| c == ' ' || c == '\t')
+		&& (line_start > 0 || indent.is_empty())
+		&& (after.starts_with('\n') || after.starts_with("\r\n"))
+}
+
+fn is_open(token: &Token) -> bool {
+	matches!(token, Token::Punct('{' | '(' | '['))
+}
+
+fn is_close(token: &Token) -> bool {
+	matches!(token, Token::Punct('}' | ')' | ']'))
+}
@@ -0,0 +1,165 @@
+//! `t4fmt` formats t4rust templates in place.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use t4rust_build::config::CrateConfig;
+use t4rust_fmt::Options;
+
+const USAGE: &str = "\
+Usage: t4fmt [--check] [--cleanws] [--spaces <N>] <PATH>...
+
+Formats the given .tt files and all .tt files in the given directories.
+
+Options:
+    --check       Lists the files which are not formatted instead of writing
+                  them and fails if there are any
+    --cleanws     The templates are compiled with cleanws, by default this is
+                  read from the crate configuration
+    --spaces <N>  Indents with N spaces instead of a tab";
+
+const TEMPLATE_EXTENSION: &str = "tt";
+
+struct Args {
+	check: bool,
+	clean_whitespace: bool,
+	indent: String,
+	paths: Vec<PathBuf>,
+}
+
+fn main() -> ExitCode {
+	let args = match parse_args(std::env::args().skip(1)) {
+		Ok(args) => args,
+		Err(err) => {
+			eprintln!("{}\n\n{}", err, USAGE);
+			return ExitCode::from(2);
+		}
+	};
+
+	let mut files = Vec::new();
+	for path in &args.paths {
+		if let Err(err) = collect_templates(path, &mut files) {
+			eprintln!("{}: {}", path.display(), err);
+			return ExitCode::FAILURE;
+		}
+	}
+
+	let mut success = true;
+	for file in files {
+		let clean_whitespace = match crate_cleanws(&file) {
+			Ok(cleanws) => args.clean_whitespace || cleanws,
+			Err(err) => {
+				eprintln!("{}", err);
+				success = false;
+				continue;
+			}
+		};
+		let options = Options { clean_whitespace, indent: args.indent.clone() };
+		let source = match std::fs::read_to_string(&file) {
+			Ok(source) => source,
+			Err(err) => {
+				eprintln!("{}: {}", file.display(), err);
+				success = false;
+				continue;
+			}
+		};
+		let formatted = match t4rust_fmt::format_file(&file, &options) {
+			Ok(formatted) => formatted,
+			Err(err) => {
+				eprintln!("{}", err);
+				success = false;
+				continue;
+			}
+		};
+		if formatted == source {
+			continue;
+		}
+
+		if args.check {
+			let line = source
+				.lines()
+				.zip(formatted.lines())
+				.position(|(a, b)| a != b)
+				.unwrap_or(0);
+			println!("{}:{}: not formatted", file.display(), line + 1);
+			success = false;
+		} else if let Err(err) = std::fs::write(&file, formatted) {
+			eprintln!("{}: {}", file.display(), err);
+			success = false;
+		}
+	}
+
+	if success { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+	let mut res = Args {
+		check: false,
+		clean_whitespace: false,
+		indent: "\t".into(),
+		paths: Vec::new(),
+	};
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--check" => res.check = true,
+			"--cleanws" => res.clean_whitespace = true,
+			"--spaces" => {
+				let spaces = args
+					.next()
+					.and_then(|n| n.parse().ok())
+					.ok_or("Expected a number after --spaces")?;
+				res.indent = " ".repeat(spaces);
+			}
+			"-h" | "--help" => return Err("Formats t4rust templates".into()),
+			_ if arg.starts_with('-') => {
+				return Err(format!("Unknown option {}", arg));
+			}
+			_ => res.paths.push(arg.into()),
+		}
+	}
+	if res.paths.is_empty() {
+		return Err("No templates given".into());
+	}
+	Ok(res)
+}
+
+/// Adds `path` if it is a file or all templates below it if it is a
+/// directory, sorted by path.
+fn collect_templates(
+	path: &Path,
+	files: &mut Vec<PathBuf>,
+) -> std::io::Result<()>
+{
+	if !path.is_dir() {
+		files.push(path.to_path_buf());
+		return Ok(());
+	}
+	let mut entries = std::fs::read_dir(path)?
+		.map(|entry| entry.map(|e| e.path()))
+		.collect::<std::io::Result<Vec<_>>>()?;
+	entries.sort();
+	for entry in entries {
+		if entry.is_dir() {
+			collect_templates(&entry, files)?;
+		} else if entry.extension().and_then(|e| e.to_str())
+			== Some(TEMPLATE_EXTENSION)
+		{
+			files.push(entry);
+		}
+	}
+	Ok(())
+}
+
+/// The `cleanws` setting of the crate which contains `file`.
+fn crate_cleanws(file: &Path) -> Result<bool, t4rust_build::Error> {
+	let file = match file.canonicalize() {
+		Ok(file) => file,
+		Err(_) => return Ok(false),
+	};
+	match file.ancestors().find(|dir| dir.join("Cargo.toml").is_file()) {
+		Some(dir) => {
+			Ok(CrateConfig::load(dir)?.clean_whitespace.unwrap_or(false))
+		}
+		None => Ok(false),
+	}
+}
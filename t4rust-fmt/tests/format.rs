@@ -0,0 +1,104 @@
+use std::path::Path;
+
+use t4rust_fmt::{format, Error, Options};
+
+fn fmt(source: &str) -> String { format(source, &Options::default()).unwrap() }
+
+#[test]
+fn pad_blocks() {
+	assert_eq!(fmt("a<#=x#>b<#   y;#>c"), "a<#= x #>b<# y; #>c");
+	// Empty and multi-line blocks are kept
+	assert_eq!(fmt("<#  #><#\n let x = 1;\n#>"), "<#  #><#\n let x = 1;\n#>");
+	// Escaped block ends stay escaped
+	assert_eq!(fmt("<#=\"#>#>\"#>"), "<#= \"#>#>\" #>");
+}
+
+#[test]
+fn directives() {
+	assert_eq!(
+		fmt("<#@template  cleanws = \"false\"   debug=\"false\"#>"),
+		"<#@ template cleanws=\"false\" debug=\"false\" #>"
+	);
+	assert_eq!(
+		fmt("<#@escape function = \"a\\\"b\" #>"),
+		"<#@ escape function=\"a\\\"b\" #>"
+	);
+}
+
+#[test]
+fn indent_code_lines() {
+	let source =
+		"<#@ template cleanws=\"true\" #>\n<# for i in 0..3 { #>\n    <# if i \
+		 > 0 { #>  \n  <#= i #>\n<# } else { #>\n-\n  <# } #>\n<# } #>\n";
+	let formatted = "<#@ template cleanws=\"true\" #>\n<# for i in 0..3 { \
+	                 #>\n\t<# if i > 0 { #>\n  <#= i #>\n\t<# } else { \
+	                 #>\n-\n\t<# } #>\n<# } #>\n";
+	assert_eq!(fmt(source), formatted);
+
+	let options = Options { clean_whitespace: true, indent: "  ".into() };
+	assert_eq!(
+		format("<# {  #>\n<# } #>\nx\n<# { #>\n<# } #>", &options).unwrap(),
+		"<# { #>\n<# } #>\nx\n<# { #>\n<# } #>"
+	);
+	assert_eq!(
+		format("<# { #>\n<# 1; #>\n<# } #>\n", &options).unwrap(),
+		"<# { #>\n  <# 1; #>\n<# } #>\n"
+	);
+}
+
+#[test]
+fn keep_rendered_whitespace() {
+	// Without cleanws the indentation is part of the output
+	let source = "<# for i in 0..3 { #>\n    <# let x = i; #>\n<# } #>\n";
+	assert_eq!(fmt(source), source);
+
+	// The first line is not cleaned if it is indented
+	let options = Options { clean_whitespace: true, ..Options::default() };
+	let source = "  <# let x = 1; #>\n";
+	assert_eq!(format(source, &options).unwrap(), source);
+}
+
+#[test]
+fn errors() {
+	match format("<# let x = 1#><#2; #>", &Options::default()) {
+		Err(Error::OutputChanged) => {}
+		res => panic!("Expected OutputChanged, got {:?}", res),
+	}
+	let err = format("a\n<#= b", &Options::default()).unwrap_err();
+	assert_eq!(
+		err.to_string(),
+		"<inline template>:2: Unclosed code or expression block"
+	);
+
+	// Templates which can not be compiled are reported, not formatted
+	for (source, line) in
+		&[("a\n<#= \"abc #>", 2), ("\n\n<#@ template cleanws=\"yes\" #>", 3)]
+	{
+		match format(source, &Options::default()) {
+			Err(Error::Template(t4rust_build::Error::Template {
+				line: l, ..
+			})) if l == *line => {}
+			res => panic!("Expected an error on line {}, got {:?}", line, res),
+		}
+	}
+}
+
+#[test]
+fn repository_templates() {
+	let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests");
+	for entry in std::fs::read_dir(dir).unwrap() {
+		let path = entry.unwrap().path();
+		if path.extension().and_then(|e| e.to_str()) != Some("tt") {
+			continue;
+		}
+		let options = Options::default();
+		let formatted = t4rust_fmt::format_file(&path, &options)
+			.unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+		assert_eq!(
+			format(&formatted, &options).unwrap(),
+			formatted,
+			"{} is not formatted idempotently",
+			path.display()
+		);
+	}
+}
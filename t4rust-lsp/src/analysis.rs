@@ -0,0 +1,213 @@
+//! The language features for a single template, independent of the protocol.
+
+use lsp_types::{
+	CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity,
+	Documentation, Hover, HoverContents, MarkupContent, MarkupKind,
+};
+use t4rust_parser::directives::{self, DirectiveInfo, ParamInfo, ParamKind};
+use t4rust_parser::{NodeKind, BLOCK_END, DIRECTIVE_START};
+
+use crate::position;
+
+/// The problems in a template, either the parse error or all problems with
+/// directives.
+pub fn diagnostics(text: &str) -> Vec<Diagnostic> {
+	let diagnostic = |span, message| Diagnostic {
+		range: position::range(text, span),
+		severity: Some(DiagnosticSeverity::ERROR),
+		source: Some("t4rust".into()),
+		message,
+		..Diagnostic::default()
+	};
+
+	match t4rust_parser::parse(text) {
+		Ok(template) => directives::validate(&template)
+			.into_iter()
+			.map(|problem| diagnostic(problem.span, problem.value))
+			.collect(),
+		Err(err) => {
+			// Mark the rest of the line where the broken block starts
+			let end = text[err.index..]
+				.find('\n')
+				.map_or(text.len(), |i| err.index + i);
+			vec![diagnostic(err.index..end, err.reason)]
+		}
+	}
+}
+
+/// Completes directive names, parameters and boolean values inside an
+/// unclosed directive before `index`.
+pub fn completion(text: &str, index: usize) -> Vec<CompletionItem> {
+	let before = &text[..index];
+	let inner = match before.rfind(DIRECTIVE_START) {
+		Some(start) => &before[start + DIRECTIVE_START.len()..],
+		None => return Vec::new(),
+	};
+	if inner.contains(BLOCK_END) {
+		return Vec::new();
+	}
+
+	let inner = inner.trim_start();
+	let name_end =
+		inner.find(|c: char| !c.is_alphanumeric()).unwrap_or(inner.len());
+	if name_end == inner.len() {
+		return directives::DIRECTIVES.iter().map(directive_item).collect();
+	}
+	let info = match directives::directive(&inner[..name_end]) {
+		Some(info) => info,
+		None => return Vec::new(),
+	};
+
+	let (keys, open_value) = scan_params(&inner[name_end..]);
+	match open_value {
+		Some(key) => match info.param(key) {
+			Some(ParamInfo { kind: ParamKind::Bool, .. }) => ["true", "false"]
+				.iter()
+				.map(|value| CompletionItem {
+					label: value.to_string(),
+					kind: Some(CompletionItemKind::VALUE),
+					..CompletionItem::default()
+				})
+				.collect(),
+			_ => Vec::new(),
+		},
+		None => info
+			.params
+			.iter()
+			.filter(|p| !keys.contains(&p.name))
+			.map(param_item)
+			.collect(),
+	}
+}
+
+/// The documentation of the directive name or parameter name at `index`.
+pub fn hover(text: &str, index: usize) -> Option<Hover> {
+	let template = t4rust_parser::parse(text).ok()?;
+	let contains = |span: &std::ops::Range<usize>| {
+		span.start <= index && index <= span.end
+	};
+	let dir = template.nodes.iter().find_map(|node| match &node.kind {
+		NodeKind::Directive(dir) if contains(&node.span) => Some(dir),
+		_ => None,
+	})?;
+	let info = directives::directive(&dir.name.value)?;
+
+	let (span, doc) = if contains(&dir.name.span) {
+		(dir.name.span.clone(), directive_doc(info))
+	} else {
+		let param = dir.params.iter().find(|p| contains(&p.name.span))?;
+		(param.name.span.clone(), param_doc(info.param(&param.name.value)?))
+	};
+	Some(Hover {
+		contents: HoverContents::Markup(markdown(doc)),
+		range: Some(position::range(text, span)),
+	})
+}
+
+/// Returns the parameter names in the directive arguments `args` and the name
+/// of the parameter whose value is not closed yet.
+fn scan_params(args: &str) -> (Vec<&str>, Option<&str>) {
+	let mut keys = Vec::new();
+	let mut in_value = false;
+	let mut escaped = false;
+	for (i, c) in args.char_indices() {
+		if in_value {
+			match c {
+				_ if escaped => escaped = false,
+				'\\' => escaped = true,
+				'"' => in_value = false,
+				_ => {}
+			}
+		} else if c == '=' {
+			let key = args[..i].trim_end();
+			let start =
+				key.rfind(|c: char| !c.is_alphanumeric()).map_or(0, |i| i + 1);
+			keys.push(&key[start..]);
+		} else if c == '"' {
+			in_value = true;
+		}
+	}
+	let open_value = if in_value { keys.last().copied() } else { None };
+	(keys, open_value)
+}
+
+fn directive_item(info: &DirectiveInfo) -> CompletionItem {
+	CompletionItem {
+		label: info.name.into(),
+		kind: Some(CompletionItemKind::KEYWORD),
+		detail: Some("directive".into()),
+		documentation: Some(Documentation::MarkupContent(markdown(
+			directive_doc(info),
+		))),
+		..CompletionItem::default()
+	}
+}
+
+fn param_item(info: &ParamInfo) -> CompletionItem {
+	CompletionItem {
+		label: info.name.into(),
+		kind: Some(CompletionItemKind::PROPERTY),
+		detail: Some(
+			if info.required { "required" } else { "optional" }.into(),
+		),
+		documentation: Some(Documentation::MarkupContent(markdown(param_doc(
+			info,
+		)))),
+		insert_text: Some(format!("{}=\"\"", info.name)),
+		..CompletionItem::default()
+	}
+}
+
+fn directive_doc(info: &DirectiveInfo) -> String {
+	let mut doc = format!("**`{}`** directive\n\n{}\n", info.name, info.doc);
+	for param in info.params {
+		doc.push_str(&format!("\n- `{}`: {}", param.name, param.doc));
+	}
+	doc
+}
+
+fn param_doc(info: &ParamInfo) -> String {
+	let kind = match info.kind {
+		ParamKind::Bool => "`true` or `false`",
+		ParamKind::Ident => "identifier",
+		ParamKind::String => "string",
+	};
+	let required = if info.required { "required" } else { "optional" };
+	format!("**`{}`** ({}, {})\n\n{}", info.name, kind, required, info.doc)
+}
+
+fn markdown(value: String) -> MarkupContent {
+	MarkupContent { kind: MarkupKind::Markdown, value }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn labels(text: &str) -> Vec<String> {
+		completion(text, text.len()).into_iter().map(|i| i.label).collect()
+	}
+
+	#[test]
+	fn complete() {
+		assert_eq!(labels("a <#@ "), ["template", "escape", "parameter"]);
+		assert_eq!(labels("<#@ templ"), ["template", "escape", "parameter"]);
+		assert_eq!(
+			labels("<#@ template strict=\"true\" "),
+			["cleanws", "clean_whitespace", "debug"]
+		);
+		assert_eq!(labels("<#@ template debug=\""), ["true", "false"]);
+		assert_eq!(labels("<#@ escape function=\""), Vec::<String>::new());
+		assert_eq!(labels("<#@ escape #> "), Vec::<String>::new());
+		assert_eq!(labels("<# code "), Vec::<String>::new());
+	}
+
+	#[test]
+	fn scan() {
+		assert_eq!(
+			scan_params(" a=\"x\" b = \"y\\\"=\" c="),
+			(vec!["a", "b", "c"], None)
+		);
+		assert_eq!(scan_params(" a=\"x\" b=\"y"), (vec!["a", "b"], Some("b")));
+	}
+}
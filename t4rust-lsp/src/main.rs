@@ -0,0 +1,173 @@
+//! A language server for t4rust templates.
+//!
+//! It talks the language server protocol over stdin and stdout and reports
+//! problems in templates while typing, completes directives and their
+//! parameters and shows their documentation on hover.
+
+mod analysis;
+mod position;
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::notification::{
+	DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+	Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, HoverRequest, Request as _};
+use lsp_types::{
+	CompletionOptions, CompletionParams, CompletionResponse,
+	HoverProviderCapability, PublishDiagnosticsParams, ServerCapabilities,
+	TextDocumentPositionParams, TextDocumentSyncCapability,
+	TextDocumentSyncKind, Url,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+fn main() -> Result<()> {
+	let (connection, io_threads) = Connection::stdio();
+
+	let capabilities = ServerCapabilities {
+		text_document_sync: Some(TextDocumentSyncCapability::Kind(
+			TextDocumentSyncKind::FULL,
+		)),
+		completion_provider: Some(CompletionOptions {
+			trigger_characters: Some(vec!["@".into(), " ".into(), "\"".into()]),
+			..CompletionOptions::default()
+		}),
+		hover_provider: Some(HoverProviderCapability::Simple(true)),
+		..ServerCapabilities::default()
+	};
+	connection.initialize(serde_json::to_value(capabilities)?)?;
+
+	Server { connection: &connection, documents: HashMap::new() }.run()?;
+	// The writer thread stops when the connection is gone
+	drop(connection);
+	io_threads.join()?;
+	Ok(())
+}
+
+struct Server<'a> {
+	connection: &'a Connection,
+	/// The content of all open documents.
+	documents: HashMap<Url, String>,
+}
+
+impl Server<'_> {
+	fn run(&mut self) -> Result<()> {
+		for msg in &self.connection.receiver {
+			match msg {
+				Message::Request(req) => {
+					if self.connection.handle_shutdown(&req)? {
+						return Ok(());
+					}
+					let res = self.request(req);
+					self.connection.sender.send(res.into())?;
+				}
+				Message::Notification(not) => self.notification(not)?,
+				Message::Response(_) => {}
+			}
+		}
+		Ok(())
+	}
+
+	fn request(&self, req: Request) -> Response {
+		let id = req.id.clone();
+		let res = match req.method.as_str() {
+			Completion::METHOD => req
+				.extract::<CompletionParams>(Completion::METHOD)
+				.map(|(_, params)| {
+					let items = self
+						.index(&params.text_document_position)
+						.map(|(text, index)| analysis::completion(text, index))
+						.unwrap_or_default();
+					serde_json::to_value(CompletionResponse::Array(items))
+				}),
+			HoverRequest::METHOD => req
+				.extract::<TextDocumentPositionParams>(HoverRequest::METHOD)
+				.map(|(_, params)| {
+					let hover = self
+						.index(&params)
+						.and_then(|(text, index)| analysis::hover(text, index));
+					serde_json::to_value(hover)
+				}),
+			_ => {
+				return Response::new_err(
+					id,
+					lsp_server::ErrorCode::MethodNotFound as i32,
+					format!("Unknown method {}", req.method),
+				);
+			}
+		};
+		match res {
+			Ok(Ok(value)) => Response { id, result: Some(value), error: None },
+			Ok(Err(err)) => Response::new_err(
+				id,
+				lsp_server::ErrorCode::InternalError as i32,
+				err.to_string(),
+			),
+			Err(err) => Response::new_err(
+				id,
+				lsp_server::ErrorCode::InvalidParams as i32,
+				err.to_string(),
+			),
+		}
+	}
+
+	fn notification(&mut self, not: Notification) -> Result<()> {
+		match not.method.as_str() {
+			DidOpenTextDocument::METHOD => {
+				let params: lsp_types::DidOpenTextDocumentParams =
+					serde_json::from_value(not.params)?;
+				let doc = params.text_document;
+				self.documents.insert(doc.uri.clone(), doc.text);
+				self.publish(doc.uri, Some(doc.version))?;
+			}
+			DidChangeTextDocument::METHOD => {
+				let params: lsp_types::DidChangeTextDocumentParams =
+					serde_json::from_value(not.params)?;
+				let doc = params.text_document;
+				// Documents are always synced in full
+				if let Some(change) = params.content_changes.into_iter().last()
+				{
+					self.documents.insert(doc.uri.clone(), change.text);
+				}
+				self.publish(doc.uri, Some(doc.version))?;
+			}
+			DidCloseTextDocument::METHOD => {
+				let params: lsp_types::DidCloseTextDocumentParams =
+					serde_json::from_value(not.params)?;
+				let uri = params.text_document.uri;
+				self.documents.remove(&uri);
+				self.publish(uri, None)?;
+			}
+			_ => {}
+		}
+		Ok(())
+	}
+
+	/// Sends the diagnostics of a document, closed documents get no
+	/// diagnostics.
+	fn publish(&self, uri: Url, version: Option<i32>) -> Result<()> {
+		let diagnostics = self
+			.documents
+			.get(&uri)
+			.map(|text| analysis::diagnostics(text))
+			.unwrap_or_default();
+		let params = PublishDiagnosticsParams { uri, diagnostics, version };
+		let not = Notification::new(PublishDiagnostics::METHOD.into(), params);
+		self.connection.sender.send(not.into())?;
+		Ok(())
+	}
+
+	/// The text of a document and the byte index of a position in it.
+	fn index(
+		&self,
+		params: &TextDocumentPositionParams,
+	) -> Option<(&str, usize)>
+	{
+		let text = self.documents.get(&params.text_document.uri)?;
+		Some((text, position::index(text, params.position)))
+	}
+}
@@ -0,0 +1,68 @@
+//! Conversion between byte indices and LSP positions, which count UTF-16 code
+//! units.
+
+use std::ops::Range;
+
+use lsp_types::Position;
+
+/// The LSP position of a byte index in `text`.
+pub fn position(text: &str, index: usize) -> Position {
+	let before = &text[..index.min(text.len())];
+	let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+	Position {
+		line: before.matches('\n').count() as u32,
+		character: before[line_start..].encode_utf16().count() as u32,
+	}
+}
+
+/// The LSP range of a byte range in `text`.
+pub fn range(text: &str, span: Range<usize>) -> lsp_types::Range {
+	lsp_types::Range {
+		start: position(text, span.start),
+		end: position(text, span.end),
+	}
+}
+
+/// The byte index of an LSP position in `text`, positions behind the end of a
+/// line are clamped to the line end.
+pub fn index(text: &str, position: Position) -> usize {
+	let line_start = if position.line == 0 {
+		0
+	} else {
+		match text.match_indices('\n').nth(position.line as usize - 1) {
+			Some((i, _)) => i + 1,
+			None => return text.len(),
+		}
+	};
+	let line = &text[line_start..];
+	let line = &line[..line.find('\n').unwrap_or(line.len())];
+
+	let mut units = 0;
+	for (i, c) in line.char_indices() {
+		if units >= position.character as usize {
+			return line_start + i;
+		}
+		units += c.len_utf16();
+	}
+	line_start + line.len()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		let text = "ab\nc😀d\n";
+		let pos = |line, character| Position { line, character };
+		assert_eq!(position(text, 0), pos(0, 0));
+		assert_eq!(position(text, 3), pos(1, 0));
+		assert_eq!(position(text, 8), pos(1, 3));
+		assert_eq!(position(text, 10), pos(2, 0));
+		for i in [0, 2, 3, 4, 8, 9, 10] {
+			assert_eq!(index(text, position(text, i)), i);
+		}
+		assert_eq!(index(text, pos(0, 10)), 2);
+		assert_eq!(index(text, pos(5, 0)), text.len());
+	}
+}
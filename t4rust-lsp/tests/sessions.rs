@@ -0,0 +1,70 @@
+//! Replays the recorded sessions in `tests/sessions` against the server.
+//!
+//! Lines starting with `-->` are sent to the server, lines starting with `<--`
+//! are the next message the server has to send. Empty lines and lines starting
+//! with `#` are ignored.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use serde_json::Value;
+
+fn replay(path: &Path) {
+	let session = std::fs::read_to_string(path).unwrap();
+	let mut server = Command::new(env!("CARGO_BIN_EXE_t4rust-lsp"))
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.spawn()
+		.unwrap();
+	let mut stdin = server.stdin.take().unwrap();
+	let mut stdout = BufReader::new(server.stdout.take().unwrap());
+
+	for (i, line) in session.lines().enumerate() {
+		let at = format!("{}:{}", path.display(), i + 1);
+		if let Some(msg) = line.strip_prefix("--> ") {
+			write!(stdin, "Content-Length: {}\r\n\r\n{}", msg.len(), msg)
+				.unwrap();
+			stdin.flush().unwrap();
+		} else if let Some(expected) = line.strip_prefix("<-- ") {
+			let expected: Value = serde_json::from_str(expected).unwrap();
+			let actual = read_message(&mut stdout)
+				.unwrap_or_else(|| panic!("{}: The server sent nothing", at));
+			assert_eq!(actual, expected, "{}", at);
+		} else {
+			assert!(line.is_empty() || line.starts_with('#'), "{}", at);
+		}
+	}
+
+	drop(stdin);
+	assert!(server.wait().unwrap().success());
+	assert_eq!(read_message(&mut stdout), None, "Unexpected message");
+}
+
+fn read_message(stdout: &mut impl BufRead) -> Option<Value> {
+	let mut len = None;
+	loop {
+		let mut header = String::new();
+		if stdout.read_line(&mut header).unwrap() == 0 {
+			return None;
+		}
+		let header = header.trim_end();
+		if header.is_empty() {
+			break;
+		}
+		if let Some(value) = header.strip_prefix("Content-Length: ") {
+			len = Some(value.parse().unwrap());
+		}
+	}
+	let mut content = vec![0; len.expect("Missing Content-Length")];
+	stdout.read_exact(&mut content).unwrap();
+	Some(serde_json::from_slice(&content).unwrap())
+}
+
+#[test]
+fn diagnostics() { replay(Path::new("tests/sessions/diagnostics.session")); }
+
+#[test]
+fn completion_hover() {
+	replay(Path::new("tests/sessions/completion_hover.session"));
+}
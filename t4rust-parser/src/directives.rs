@@ -0,0 +1,232 @@
+//! The directives the template compiler understands and their parameters.
+
+use crate::{Directive, NodeKind, Spanned, Template};
+
+/// A directive and its documentation.
+#[derive(Debug)]
+pub struct DirectiveInfo {
+	pub name: &'static str,
+	pub doc: &'static str,
+	pub params: &'static [ParamInfo],
+}
+
+/// A parameter of a directive and its documentation.
+#[derive(Debug)]
+pub struct ParamInfo {
+	pub name: &'static str,
+	pub doc: &'static str,
+	pub kind: ParamKind,
+	/// The parameter has to be given.
+	pub required: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamKind {
+	/// `true` or `false`
+	Bool,
+	/// A Rust identifier
+	Ident,
+	/// Any value
+	String,
+}
+
+pub const DIRECTIVES: &[DirectiveInfo] = &[
+	DirectiveInfo {
+		name: "template",
+		doc: "Changes the settings of the template from here on.",
+		params: &[
+			ParamInfo {
+				name: "cleanws",
+				doc: "Removes the line breaks and indentation around code and \
+				      directive blocks which are alone on their line.",
+				kind: ParamKind::Bool,
+				required: false,
+			},
+			ParamInfo {
+				name: "clean_whitespace",
+				doc: "Same as `cleanws`.",
+				kind: ParamKind::Bool,
+				required: false,
+			},
+			ParamInfo {
+				name: "strict",
+				doc: "Enables strict escaping, unescaped output has to be \
+				      marked with `raw!(...)`. It can not be disabled again.",
+				kind: ParamKind::Bool,
+				required: false,
+			},
+			ParamInfo {
+				name: "debug",
+				doc: "Writes the parsed parts and the generated code to \
+				      `target/t4rust/<crate>/<key>/`, the key is the name of \
+				      the struct with its module path or template path.",
+				kind: ParamKind::Bool,
+				required: false,
+			},
+		],
+	},
+	DirectiveInfo {
+		name: "escape",
+		doc: "Sets the function which escapes the output of expression blocks \
+		      from here on.",
+		params: &[ParamInfo {
+			name: "function",
+			doc: "A path to a `fn(&str) -> String`, the built-in `html` or \
+			      `xml` escapers, or `none` (or empty) to disable escaping.",
+			kind: ParamKind::String,
+			required: true,
+		}],
+	},
+	DirectiveInfo {
+		name: "parameter",
+		doc: "Declares an input of the template, it becomes a field of the \
+		      struct generated by `template_struct!`.",
+		params: &[
+			ParamInfo {
+				name: "name",
+				doc: "The name of the field.",
+				kind: ParamKind::Ident,
+				required: true,
+			},
+			ParamInfo {
+				name: "type",
+				doc: "The Rust type of the field.",
+				kind: ParamKind::String,
+				required: true,
+			},
+			ParamInfo {
+				name: "default",
+				doc: "A Rust expression for the default value, the parameter \
+				      is optional then.",
+				kind: ParamKind::String,
+				required: false,
+			},
+		],
+	},
+];
+
+/// Looks up a directive by name.
+pub fn directive(name: &str) -> Option<&'static DirectiveInfo> {
+	DIRECTIVES.iter().find(|d| d.name == name)
+}
+
+impl DirectiveInfo {
+	/// Looks up a parameter by name.
+	pub fn param(&self, name: &str) -> Option<&'static ParamInfo> {
+		self.params.iter().find(|p| p.name == name)
+	}
+}
+
+/// Checks the directives of a template, the problems are returned with the
+/// span they refer to.
+pub fn validate(template: &Template) -> Vec<Spanned<String>> {
+	let mut problems = Vec::new();
+	for node in &template.nodes {
+		if let NodeKind::Directive(dir) = &node.kind {
+			validate_directive(dir, &mut problems);
+		}
+	}
+	problems
+}
+
+fn validate_directive(dir: &Directive, problems: &mut Vec<Spanned<String>>) {
+	let params: Vec<_> = dir
+		.params
+		.iter()
+		.map(|p| {
+			let name = (p.name.value.as_str(), &p.name.span);
+			(name, (p.value.value.as_str(), &p.value.span))
+		})
+		.collect();
+	let name = (dir.name.value.as_str(), &dir.name.span);
+	for (span, value) in directive_problems(name, &params) {
+		problems.push(Spanned { value, span: span.clone() });
+	}
+}
+
+/// Checks a directive without spans, e.g. one which the compiler parsed, and
+/// returns the first problem.
+pub fn check<'a>(
+	name: &str,
+	params: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<(), String>
+{
+	let params: Vec<_> = params
+		.into_iter()
+		.map(|(key, value)| ((key, ()), (value, ())))
+		.collect();
+	match directive_problems((name, ()), &params).into_iter().next() {
+		Some(((), problem)) => Err(problem),
+		None => Ok(()),
+	}
+}
+
+/// A name or value and where it is located.
+type Located<'a, L> = (&'a str, L);
+
+/// The problems of a directive, each with the location `L` of the name or
+/// value it refers to.
+fn directive_problems<L: Copy>(
+	(name, name_at): Located<L>,
+	params: &[(Located<L>, Located<L>)],
+) -> Vec<(L, String)>
+{
+	let info = match directive(name) {
+		Some(info) => info,
+		None => {
+			return vec![(name_at, format!("Unknown directive `{}`", name))]
+		}
+	};
+
+	let mut problems = Vec::new();
+	for &((key, key_at), (value, value_at)) in params {
+		let param_info = match info.param(key) {
+			Some(param_info) => param_info,
+			None => {
+				problems.push((
+					key_at,
+					format!(
+						"Unknown parameter `{}` for the `{}` directive",
+						key, info.name
+					),
+				));
+				continue;
+			}
+		};
+		let valid = match param_info.kind {
+			ParamKind::Bool => value == "true" || value == "false",
+			ParamKind::Ident => is_ident(value),
+			ParamKind::String => true,
+		};
+		if !valid {
+			let expected = match param_info.kind {
+				ParamKind::Bool => "`true` or `false`",
+				_ => "an identifier",
+			};
+			problems.push((
+				value_at,
+				format!("Expected {} for `{}`", expected, param_info.name),
+			));
+		}
+	}
+
+	for param_info in info.params.iter().filter(|p| p.required) {
+		if !params.iter().any(|((key, _), _)| *key == param_info.name) {
+			problems.push((
+				name_at,
+				format!(
+					"The `{}` directive needs the parameter `{}`",
+					info.name, param_info.name
+				),
+			));
+		}
+	}
+	problems
+}
+
+fn is_ident(value: &str) -> bool {
+	let mut chars = value.chars();
+	chars.next().is_some_and(|c| c == '_' || c.is_alphabetic())
+		&& chars.all(|c| c == '_' || c.is_alphanumeric())
+		&& value != "_"
+}
@@ -0,0 +1,372 @@
+//! The template parser of [t4rust](https://github.com/ReSpeak/t4rust).
+//!
+//! [`parse`] splits a template into text, code, expression and directive
+//! nodes. Every node knows its byte range in the source, the nodes cover the
+//! whole source without gaps, so printing a [`Template`] reproduces its source
+//! byte for byte.
+//!
+//! ```
+//! use t4rust_parser::NodeKind;
+//!
+//! let source = "<#@ escape function=\"html\" #>Hello <#= name #>!";
+//! let template = t4rust_parser::parse(source).unwrap();
+//!
+//! match &template.nodes[0].kind {
+//!     NodeKind::Directive(dir) => {
+//!         assert_eq!(dir.name.value, "escape");
+//!         assert_eq!(dir.param("function"), Some("html"));
+//!     }
+//!     _ => unreachable!(),
+//! }
+//! assert_eq!(&source[template.nodes[2].span.clone()], "<#= name #>");
+//! assert_eq!(template.to_string(), source);
+//! ```
+
+pub mod directives;
+
+use std::fmt;
+use std::ops::Range;
+
+use nom::{
+	bytes::complete::{escaped, is_not, tag, take_until, take_while1},
+	character::complete::{alphanumeric1, one_of, space0},
+	combinator::{not, opt},
+	sequence::tuple,
+	IResult,
+};
+
+/// The start of a code block.
+pub const CODE_START: &str = "<#";
+/// The start of an expression block.
+pub const EXPRESSION_START: &str = "<#=";
+/// The start of a directive block.
+pub const DIRECTIVE_START: &str = "<#@";
+/// The end of all blocks.
+pub const BLOCK_END: &str = "#>";
+
+/// A parsed template.
+#[derive(Debug, Clone)]
+pub struct Template<'a> {
+	pub source: &'a str,
+	pub nodes: Vec<Node>,
+}
+
+/// A node of a template and its byte range in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+	pub span: Range<usize>,
+	pub kind: NodeKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeKind {
+	/// Template text, `value` contains `<#` instead of the escaped `<#<#`.
+	Text { value: String },
+	/// A code block, `<# ... #>`.
+	Code(Block),
+	/// An expression block, `<#= ... #>`.
+	Expr(Block),
+	/// A directive block, `<#@ name key="value" #>`.
+	Directive(Directive),
+}
+
+/// The content of a block between its delimiters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+	/// The byte range of the content in the source.
+	pub content: Range<usize>,
+	/// The content with `#>` instead of the escaped `#>#>`.
+	pub value: String,
+}
+
+/// A directive and its parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Directive {
+	pub block: Block,
+	pub name: Spanned<String>,
+	pub params: Vec<Param>,
+}
+
+/// A parameter of a directive, `key="value"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+	/// The byte range of the whole parameter.
+	pub span: Range<usize>,
+	pub name: Spanned<String>,
+	/// The value without quotes and escapes, the span is the range between
+	/// the quotes.
+	pub value: Spanned<String>,
+}
+
+/// A value and its byte range in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+	pub value: T,
+	pub span: Range<usize>,
+}
+
+/// An error in a template, `index` is the byte position in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+	pub reason: String,
+	pub index: usize,
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.reason)
+	}
+}
+
+impl std::error::Error for ParseError {}
+
+impl Template<'_> {
+	/// Returns the 1-based line and column (in characters) of a byte index.
+	pub fn line_col(&self, index: usize) -> (usize, usize) {
+		line_col(self.source, index)
+	}
+}
+
+/// Prints the template back into its source.
+impl fmt::Display for Template<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for node in &self.nodes {
+			f.write_str(&self.source[node.span.clone()])?;
+		}
+		Ok(())
+	}
+}
+
+impl Node {
+	/// The content of a code, expression or directive block.
+	pub fn block(&self) -> Option<&Block> {
+		match &self.kind {
+			NodeKind::Text { .. } => None,
+			NodeKind::Code(block) | NodeKind::Expr(block) => Some(block),
+			NodeKind::Directive(dir) => Some(&dir.block),
+		}
+	}
+}
+
+impl Block {
+	/// Maps a byte index in `value` to the index in the source.
+	pub fn source_index(&self, source: &str, index: usize) -> usize {
+		let raw = &source[self.content.clone()];
+		let mut raw_index = 0;
+		let mut value_index = 0;
+		while value_index < index && raw_index < raw.len() {
+			let (raw_len, value_len) = if raw[raw_index..].starts_with("#>#>") {
+				(4, 2)
+			} else {
+				let len = raw[raw_index..].chars().next().unwrap().len_utf8();
+				(len, len)
+			};
+			if value_index + value_len > index {
+				break;
+			}
+			raw_index += raw_len;
+			value_index += value_len;
+		}
+		self.content.start + raw_index + (index - value_index)
+	}
+}
+
+impl Directive {
+	/// The value of the first parameter called `name`.
+	pub fn param(&self, name: &str) -> Option<&str> {
+		self.params
+			.iter()
+			.find(|p| p.name.value == name)
+			.map(|p| p.value.value.as_str())
+	}
+}
+
+/// Returns the 1-based line and column (in characters) of a byte index in
+/// `source`.
+pub fn line_col(source: &str, index: usize) -> (usize, usize) {
+	let before = &source[..index.min(source.len())];
+	let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+	(before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+/// Parses a template.
+pub fn parse(source: &str) -> Result<Template<'_>, ParseError> {
+	let mut nodes = Vec::new();
+	let mut index = 0;
+
+	while index < source.len() {
+		let (end, value) = parse_text(source, index);
+		if end > index {
+			nodes.push(Node {
+				span: index..end,
+				kind: NodeKind::Text { value },
+			});
+			index = end;
+		}
+		if index == source.len() {
+			break;
+		}
+
+		let start = index;
+		let rest = &source[index..];
+		let open = if rest.starts_with(EXPRESSION_START) {
+			EXPRESSION_START
+		} else if rest.starts_with(DIRECTIVE_START) {
+			DIRECTIVE_START
+		} else {
+			CODE_START
+		};
+		let block =
+			parse_block(source, start + open.len()).ok_or_else(|| {
+				ParseError {
+					index: start,
+					reason: "Unclosed code or expression block".into(),
+				}
+			})?;
+		let end = block.content.end + BLOCK_END.len();
+		let kind = match open {
+			EXPRESSION_START => NodeKind::Expr(block),
+			DIRECTIVE_START => {
+				NodeKind::Directive(parse_directive(source, block).map_err(
+					|content| ParseError {
+						index: start,
+						reason: format!(
+							"Could not understand the directive: {}",
+							content
+						),
+					},
+				)?)
+			}
+			_ => NodeKind::Code(block),
+		};
+		nodes.push(Node { span: start..end, kind });
+		index = end;
+	}
+
+	Ok(Template { source, nodes })
+}
+
+/// Reads text until the next block, returns the end of the text.
+fn parse_text(source: &str, start: usize) -> (usize, String) {
+	let mut value = String::new();
+	let mut index = start;
+	loop {
+		let rest = &source[index..];
+		match read_text(rest) {
+			Ok((_, done)) => {
+				value.push_str(done);
+				index += done.len();
+				if double_code_start(&source[index..]).is_ok() {
+					value.push_str(CODE_START);
+					index += 4;
+				} else {
+					return (index, value);
+				}
+			}
+			// No more blocks
+			Err(_) => {
+				value.push_str(rest);
+				return (source.len(), value);
+			}
+		}
+	}
+}
+
+/// Reads the content of a block until its end, `None` if it is not closed.
+fn parse_block(source: &str, start: usize) -> Option<Block> {
+	let mut value = String::new();
+	let mut index = start;
+	loop {
+		let (_, done) = read_code(&source[index..]).ok()?;
+		value.push_str(done);
+		index += done.len();
+		if code_end(&source[index..]).is_ok() {
+			return Some(Block { content: start..index, value });
+		}
+		// Escaped `#>#>`
+		value.push_str(BLOCK_END);
+		index += 4;
+	}
+}
+
+/// Parses the name and parameters of a directive, the content is returned as
+/// error if it is malformed.
+fn parse_directive(source: &str, block: Block) -> Result<Directive, String> {
+	let content = &block.value;
+	// Positions are mapped from the unescaped content to the source
+	let span = |part: &str| {
+		let start = part.as_ptr() as usize - content.as_ptr() as usize;
+		block.source_index(source, start)
+			..block.source_index(source, start + part.len())
+	};
+	let error = || content.clone();
+
+	let (mut rest, (_, name)) =
+		tuple((space0, alphanumeric1))(content.as_str())
+			.map_err(|_: nom::Err<()>| error())?;
+	let name = Spanned { value: name.to_string(), span: span(name) };
+
+	let mut params = Vec::new();
+	while !rest.trim().is_empty() {
+		let (next, (_, key, _, _, _, quote, raw, _)) =
+			directive_param(rest).map_err(|_| error())?;
+		let param = rest[..rest.len() - next.len()].trim();
+		// An empty value is an empty slice behind the opening quote
+		let raw = raw.unwrap_or(&quote[1..]);
+		params.push(Param {
+			span: span(param),
+			name: Spanned { value: key.to_string(), span: span(key) },
+			value: Spanned { value: unescape(raw), span: span(raw) },
+		});
+		rest = next;
+	}
+
+	Ok(Directive { block, name, params })
+}
+
+/// Removes the `\` from `\"` and `\\` in a parameter value.
+fn unescape(raw: &str) -> String {
+	let mut res = String::with_capacity(raw.len());
+	let mut chars = raw.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			'\\' => res.extend(chars.next()),
+			c => res.push(c),
+		}
+	}
+	res
+}
+
+// NOM DECLARATIONS ===========================================================
+
+fn read_text(s: &str) -> IResult<&str, &str> { take_until(CODE_START)(s) }
+fn double_code_start(s: &str) -> IResult<&str, &str> { tag("<#<#")(s) }
+
+fn code_end(s: &str) -> IResult<&str, &str> {
+	let (s, r) = tag(BLOCK_END)(s)?;
+	not(tag(BLOCK_END))(s)?;
+	Ok((s, r))
+}
+
+fn read_code(s: &str) -> IResult<&str, &str> { take_until(BLOCK_END)(s) }
+
+#[allow(clippy::type_complexity)]
+fn directive_param(
+	s: &str,
+) -> IResult<&str, (&str, &str, &str, &str, &str, &str, Option<&str>, &str)>
+{
+	let (s, param) = tuple((
+		space0,
+		take_while1(|c: char| c.is_alphanumeric() || c == '_'),
+		space0,
+		tag("="),
+		space0,
+		tag("\""),
+		opt(escaped(is_not("\\\""), '\\', one_of("\\\""))),
+		tag("\""),
+	))(s)?;
+	let (s, _) = space0(s)?;
+	Ok((s, param))
+}
+
+// NOM END ====================================================================
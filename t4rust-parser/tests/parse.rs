@@ -0,0 +1,151 @@
+use t4rust_parser::{directives, parse, NodeKind};
+
+const TEMPLATE: &str = "<#@ template cleanws=\"true\" #>\nHello <#= self.name \
+                        #>!\n<# for i in 0..3 { #>\n<#<# <#= i #>\n<# } #><#@ \
+                        escape  function = \"a\\\"b\" #>\n<# let s = \
+                        \"#>#>\"; #>tail";
+
+#[test]
+fn round_trip() {
+	let template = parse(TEMPLATE).unwrap();
+	assert_eq!(template.to_string(), TEMPLATE);
+
+	// The nodes cover the source without gaps
+	let mut index = 0;
+	for node in &template.nodes {
+		assert_eq!(node.span.start, index);
+		index = node.span.end;
+	}
+	assert_eq!(index, TEMPLATE.len());
+
+	assert_eq!(parse("").unwrap().nodes, vec![]);
+}
+
+#[test]
+fn nodes() {
+	let template = parse(TEMPLATE).unwrap();
+	let src = |span: std::ops::Range<usize>| &TEMPLATE[span];
+
+	let kinds: Vec<_> = template
+		.nodes
+		.iter()
+		.map(|n| match &n.kind {
+			NodeKind::Text { .. } => "text",
+			NodeKind::Code(_) => "code",
+			NodeKind::Expr(_) => "expr",
+			NodeKind::Directive(_) => "directive",
+		})
+		.collect();
+	assert_eq!(
+		kinds,
+		[
+			"directive",
+			"text",
+			"expr",
+			"text",
+			"code",
+			"text",
+			"expr",
+			"text",
+			"code",
+			"directive",
+			"text",
+			"code",
+			"text",
+		]
+	);
+
+	// Escapes are removed from the values, but not from the spans
+	let text = &template.nodes[5];
+	assert_eq!(src(text.span.clone()), "\n<#<# ");
+	assert_eq!(text.kind, NodeKind::Text { value: "\n<# ".into() });
+
+	let code = template.nodes[11].block().unwrap();
+	assert_eq!(code.value, " let s = \"#>\"; ");
+	assert_eq!(src(code.content.clone()), " let s = \"#>#>\"; ");
+	assert_eq!(code.source_index(TEMPLATE, 11), code.content.start + 11);
+	assert_eq!(code.source_index(TEMPLATE, 13), code.content.start + 15);
+
+	let expr = template.nodes[2].block().unwrap();
+	assert_eq!(expr.value, " self.name ");
+	assert_eq!(src(expr.content.clone()), " self.name ");
+}
+
+#[test]
+fn directives() {
+	let template = parse(TEMPLATE).unwrap();
+	let src = |span: std::ops::Range<usize>| &TEMPLATE[span];
+
+	let dir = match &template.nodes[9].kind {
+		NodeKind::Directive(dir) => dir,
+		_ => panic!("Expected a directive"),
+	};
+	assert_eq!(dir.name.value, "escape");
+	assert_eq!(src(dir.name.span.clone()), "escape");
+	assert_eq!(dir.params.len(), 1);
+	let param = &dir.params[0];
+	assert_eq!(src(param.span.clone()), "function = \"a\\\"b\"");
+	assert_eq!(src(param.name.span.clone()), "function");
+	assert_eq!(param.value.value, "a\"b");
+	assert_eq!(src(param.value.span.clone()), "a\\\"b");
+	assert_eq!(dir.param("function"), Some("a\"b"));
+
+	let template = parse("<#@ template debug=\"\" #>").unwrap();
+	let dir = match &template.nodes[0].kind {
+		NodeKind::Directive(dir) => dir,
+		_ => panic!("Expected a directive"),
+	};
+	assert_eq!(dir.param("debug"), Some(""));
+	assert_eq!(dir.params[0].value.span, 20..20);
+
+	// Keys may contain underscores like the `clean_whitespace` alias
+	let template =
+		parse("<#@ template clean_whitespace=\"true\" #>").unwrap();
+	assert!(directives::validate(&template).is_empty());
+}
+
+#[test]
+fn errors() {
+	let err = parse("a\n<#= b").unwrap_err();
+	assert_eq!(err.index, 2);
+	assert_eq!(err.reason, "Unclosed code or expression block");
+
+	let err = parse("<# a #><#@ escape function #>").unwrap_err();
+	assert_eq!(err.index, 7);
+	assert_eq!(
+		err.reason,
+		"Could not understand the directive:  escape function "
+	);
+
+	assert_eq!(t4rust_parser::line_col("ab\ncäd", 6), (2, 3));
+}
+
+#[test]
+fn validate_directives() {
+	const TEMPLATE: &str = "<#@ template strict=\"yes\" color=\"red\" #><#@ \
+	                        parameter name=\"1a\" #><#@ include #>";
+	let template = parse(TEMPLATE).unwrap();
+	let problems: Vec<_> = directives::validate(&template)
+		.into_iter()
+		.map(|p| (p.value, &TEMPLATE[p.span]))
+		.collect();
+	assert_eq!(
+		problems,
+		[
+			("Expected `true` or `false` for `strict`".to_string(), "yes"),
+			(
+				"Unknown parameter `color` for the `template` directive".into(),
+				"color"
+			),
+			("Expected an identifier for `name`".into(), "1a"),
+			(
+				"The `parameter` directive needs the parameter `type`".into(),
+				"parameter"
+			),
+			("Unknown directive `include`".into(), "include"),
+		]
+	);
+
+	let template = parse("<#@ escape function=\"html\" #>").unwrap();
+	assert!(directives::validate(&template).is_empty());
+}
@@ -0,0 +1,11 @@
+use t4rust_derive::Template;
+
+#[derive(Template)]
+#[TemplatePath = "./tests/bracket_balance.tt"]
+struct BracketBalance;
+
+#[test]
+fn bracket_balance() {
+	let f = format!("{}", BracketBalance);
+	assert_eq!(f, "0: { 5\n1: ( 5\n2: [ 5\n");
+}
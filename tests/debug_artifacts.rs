@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use t4rust_derive::Template;
+
+#[derive(Template)]
+#[template(path = "./tests/debug_artifacts.tt", cleanws, debug)]
+struct DebugFirst {
+	name: &'static str,
+}
+
+// Shares the template, but gets its own artifacts
+#[derive(Template)]
+#[template(path = "./tests/debug_artifacts.tt", cleanws, debug)]
+struct DebugSecond {
+	name: &'static str,
+}
+
+/// The artifact directory of the struct called `name` in this file, the
+/// file is the root of the test crate.
+fn artifacts(name: &str) -> PathBuf {
+	let target = option_env!("CARGO_TARGET_DIR").map_or_else(
+		|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target"),
+		PathBuf::from,
+	);
+	target.join("t4rust").join(env!("CARGO_CRATE_NAME")).join(name)
+}
+
+#[test]
+fn debug_artifacts() {
+	assert_eq!(DebugFirst { name: "t4" }.to_string(), "Hello t4!\n");
+	assert_eq!(DebugSecond { name: "" }.to_string(), "Hello !\nNobody\n");
+
+	for name in &["DebugFirst", "DebugSecond"] {
+		let dir = artifacts(name);
+		let code = std::fs::read_to_string(dir.join("code.rs")).unwrap();
+		assert!(
+			code.contains(&format!("impl ::std::fmt::Display for {}", name))
+		);
+
+		let parts =
+			std::fs::read_to_string(dir.join("tests/debug_artifacts.tt.json"))
+				.unwrap();
+		assert!(parts.contains("\"template\": "));
+		assert!(parts.contains("\"parsed\": ["));
+		assert!(parts.contains("\"postprocessed\": ["));
+		assert!(parts.contains("\"optimized\": ["));
+		assert!(parts.contains("\"value\": \"Nobody\\n\""));
+	}
+	// No more files next to the template
+	assert!(!PathBuf::from("tests/debug_artifacts.tt.out").exists());
+}
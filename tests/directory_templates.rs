@@ -0,0 +1,25 @@
+use t4rust_derive::templates;
+
+pub struct Item {
+	name: &'static str,
+	count: u32,
+}
+
+templates!("./tests/templates/mail");
+
+#[test]
+fn directory_templates() {
+	let welcome = welcome::Welcome { name: "<Splamy>".into() };
+	assert_eq!(
+		welcome.to_string().trim_end_matches(['\r', '\n']),
+		"<p>Welcome &lt;Splamy&gt;!</p>"
+	);
+
+	let shipped = order_shipped::OrderShipped {
+		items: vec![Item { name: "Cake", count: 2 }],
+	};
+	assert_eq!(shipped.to_string(), "2x Cake\n");
+
+	let report = admin::report::Report::default();
+	assert_eq!(report.to_string().trim_end_matches(['\r', '\n']), "Users: 0");
+}
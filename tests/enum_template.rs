@@ -0,0 +1,32 @@
+use t4rust_derive::Template;
+
+#[derive(Template)]
+#[template(escape = "html")]
+enum Notification {
+	#[template(path = "./tests/enum_welcome.tt")]
+	Welcome { name: String, unused: u32 },
+	#[template(path = "./tests/enum_invoice.tt")]
+	Invoice(u32, Vec<&'static str>),
+	#[template(path = "./tests/enum_bye.tt", escape = "none")]
+	Bye,
+}
+
+// Compiles although it can not be rendered
+#[derive(Template)]
+#[allow(dead_code)]
+enum Never {}
+
+impl Notification {
+	fn kind(&self) -> &'static str { "<bye>" }
+}
+
+#[test]
+fn enum_template() {
+	let welcome = Notification::Welcome { name: "<Splamy>".into(), unused: 0 };
+	assert_eq!(format!("{}", welcome), "Welcome &lt;Splamy&gt;!\n");
+
+	let invoice = Notification::Invoice(7, vec!["Cake", "Tea"]);
+	assert_eq!(format!("{}", invoice), "Invoice 7:\n - Cake\n - Tea\n");
+
+	assert_eq!(format!("{}", Notification::Bye), "Bye <bye>\n");
+}
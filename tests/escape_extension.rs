@@ -0,0 +1,27 @@
+use t4rust_derive::Template;
+
+#[derive(Template)]
+#[template(path = "./tests/escape_extension.html.tt")]
+struct Html(&'static str);
+
+#[derive(Template)]
+#[template(path = "./tests/escape_extension.html.tt", escape = "none")]
+struct HtmlUnescaped(&'static str);
+
+#[derive(Template)]
+#[template(path = "./tests/escape_extension.xml.tt")]
+struct Xml(&'static str);
+
+#[test]
+fn escape_from_extension() {
+	assert_eq!(
+		format!("{}", Html("<'&'>")),
+		"<p>&lt;&#x27;&amp;&#x27;&gt;</p>\n"
+	);
+	assert_eq!(format!("{}", Xml("'\"")), "<a>&apos;&quot;</a>\n<b>'\"</b>\n");
+}
+
+#[test]
+fn escape_none() {
+	assert_eq!(format!("{}", HtmlUnescaped("<i>")), "<p><i></p>\n");
+}
@@ -0,0 +1,14 @@
+use t4rust_derive::Template;
+
+#[derive(Template)]
+#[TemplatePath = "./tests/formatter_hygiene.tt"]
+struct FormatterHygiene {
+	name: String,
+}
+
+#[test]
+fn formatter_hygiene() {
+	let f = format!("{}", FormatterHygiene { name: "out".into() });
+	let f = f.trim_end_matches(['\r', '\n']);
+	assert_eq!(f, "local 1 legacy OUT");
+}
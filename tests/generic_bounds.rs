@@ -0,0 +1,115 @@
+use std::fmt::{Debug, Display};
+use std::marker::PhantomData;
+
+use t4rust_derive::Template;
+
+#[derive(Template)]
+#[template(path = "./tests/generic_bounds.tt")]
+struct List<T> {
+	items: Vec<T>,
+}
+
+#[derive(Template)]
+#[template(path = "./tests/generic_bounds.tt")]
+struct Tagged<T, M> {
+	items: Vec<T>,
+	marker: PhantomData<M>,
+}
+
+#[derive(Template)]
+#[template(path = "./tests/generic_bounds_len.tt", bound = "")]
+struct Counted<T> {
+	items: Vec<T>,
+}
+
+// Only printed values need a bound
+#[derive(Template)]
+#[template(path = "./tests/generic_bounds_len.tt")]
+struct Unbounded<T> {
+	items: Vec<T>,
+}
+
+#[derive(Template)]
+enum Either<T, U> {
+	#[template(path = "./tests/generic_bounds_value.tt")]
+	Left { value: T },
+	#[template(path = "./tests/generic_bounds_items.tt")]
+	Right { items: Vec<U> },
+}
+
+#[derive(Template)]
+#[template(path = "./tests/generic_bounds_len.tt")]
+struct Bounded<T: Debug> {
+	items: Vec<T>,
+}
+
+// Other bounds do not replace the inferred one
+#[derive(Template)]
+#[template(path = "./tests/generic_bounds.tt")]
+struct Cloned<T: Clone> {
+	items: Vec<T>,
+}
+
+#[derive(Template)]
+#[template(path = "./tests/generic_bounds.tt")]
+struct Sorted<T>
+where T: Ord
+{
+	items: Vec<T>,
+}
+
+#[derive(Template)]
+#[template(path = "./tests/generic_bounds.tt")]
+struct Shown<T: Display> {
+	items: Vec<T>,
+}
+
+#[derive(Template)]
+#[template(path = "./tests/generic_bounds.tt", bound = "T: Display + Copy")]
+struct Copied<T> {
+	items: Vec<T>,
+}
+
+// The bound of a named template only applies to its own methods
+#[derive(Template)]
+#[template(path = "./tests/generic_bounds.tt")]
+#[template(
+	name = "count",
+	path = "./tests/generic_bounds_len.tt",
+	bound = "T: Copy"
+)]
+struct Both<T> {
+	items: Vec<T>,
+}
+
+#[derive(Clone, Copy)]
+struct NoDisplay;
+
+#[test]
+fn inferred_display_bound() {
+	assert_eq!(List { items: vec![1, 2] }.to_string(), "1;2;");
+	let tagged = Tagged { items: vec!["a"], marker: PhantomData::<NoDisplay> };
+	assert_eq!(tagged.to_string(), "a;");
+
+	let unbounded = Unbounded { items: vec![NoDisplay] };
+	assert_eq!(unbounded.to_string(), "1 items");
+	let left = Either::<_, NoDisplay>::Left { value: 'x' };
+	assert_eq!(left.to_string(), "x");
+	let right = Either::<char, _>::Right { items: vec![NoDisplay] };
+	assert_eq!(right.to_string(), "1 items");
+
+	assert_eq!(Cloned { items: vec![1, 2] }.to_string(), "1;2;");
+	assert_eq!(Sorted { items: vec!['a'] }.to_string(), "a;");
+	assert_eq!(Shown { items: vec![1] }.to_string(), "1;");
+}
+
+#[test]
+fn explicit_bound() {
+	let counted = Counted { items: vec![NoDisplay, NoDisplay] };
+	assert_eq!(counted.to_string(), "2 items");
+	assert_eq!(Bounded { items: vec![()] }.to_string(), "1 items");
+	assert_eq!(Copied { items: vec!['x'] }.to_string(), "x;");
+
+	assert_eq!(Both { items: vec![1] }.to_string(), "1;");
+	assert_eq!(Both { items: vec![NoDisplay] }.render_count(), "1 items");
+}
@@ -0,0 +1,14 @@
+use t4rust_derive::Template;
+
+#[derive(Template)]
+#[template(path = "./tests/hot_reload.tt", cleanws, hot_reload)]
+struct HotReload {
+	count: usize,
+}
+
+// Changes to the template file are tested on a copy by `t4rust-build`
+#[test]
+fn hot_reload() {
+	let template = HotReload { count: 2 };
+	assert_eq!(template.to_string(), "  Item 0\n  Item 1\nDone\n");
+}
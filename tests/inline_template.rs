@@ -0,0 +1,31 @@
+use t4rust_derive::t4;
+
+struct Order {
+	items: Vec<&'static str>,
+}
+
+impl Order {
+	fn summary(&self) -> String {
+		t4!("<# for item in &self.items { #>- <#= item #>\n<# } #>").to_string()
+	}
+}
+
+#[test]
+fn inline_template() {
+	let name = String::from("Splamy");
+	let count = 3;
+	let text = t4!("Dear <#= name #>, you have <#= count #> new messages.");
+	assert_eq!(text.to_string(), "Dear Splamy, you have 3 new messages.");
+	// The locals are only borrowed
+	assert_eq!(name, "Splamy");
+
+	let order = Order { items: vec!["Cake", "Tea"] };
+	assert_eq!(order.summary(), "- Cake\n- Tea\n");
+}
+
+#[test]
+fn inline_template_escape() {
+	let name = "<b>";
+	let text = t4!("<#@ escape function=\"html\" #><#= name #>");
+	assert_eq!(format!("{}", text), "&lt;b&gt;");
+}
@@ -0,0 +1,16 @@
+#![allow(deprecated)]
+
+use t4rust_derive::Template;
+
+#[derive(Template)]
+#[TemplatePath = "./tests/simple_template.tt"]
+struct LegacyAttributes {
+	text: String,
+}
+
+#[test]
+fn legacy_attributes() {
+	let f = format!("{}", LegacyAttributes { text: "Inner".into() });
+	let f = f.trim_end_matches(['\r', '\n']);
+	assert_eq!(f, "Text Inner Other Text");
+}
@@ -0,0 +1,40 @@
+use t4rust_derive::Template;
+
+#[derive(Template)]
+#[template(path = "./tests/named_templates.txt.tt")]
+#[template(name = "html", path = "./tests/named_templates.html.tt")]
+#[template(name = "subject", path = "./tests/named_templates_subject.tt")]
+struct Mail {
+	name: &'static str,
+	body: &'static str,
+}
+
+#[derive(Template)]
+#[template(name = "html", path = "./tests/named_templates.html.tt")]
+struct HtmlOnly<T: std::fmt::Display> {
+	name: T,
+	body: &'static str,
+}
+
+#[test]
+fn named_templates() {
+	let mail = Mail { name: "<Splamy>", body: "a & b" };
+	assert_eq!(
+		format!("{}", mail).trim_end_matches(['\r', '\n']),
+		"Hello <Splamy>,\na & b"
+	);
+	assert_eq!(
+		mail.render_html().trim_end_matches(['\r', '\n']),
+		"<p>Hello &lt;Splamy&gt;,</p>\n<p>a &amp; b</p>"
+	);
+	assert_eq!(format!("[{}]", mail.as_subject()), "[News for <Splamy>]");
+}
+
+#[test]
+fn named_templates_without_display() {
+	let mail = HtmlOnly { name: 42, body: "" };
+	assert_eq!(
+		mail.as_html().to_string().trim_end_matches(['\r', '\n']),
+		"<p>Hello 42,</p>\n<p></p>"
+	);
+}
@@ -0,0 +1,48 @@
+use std::fmt::{self, Write};
+
+use t4rust_derive::{t4, Template};
+
+#[derive(Template)]
+#[template(path = "./tests/padding.tt")]
+struct Padding(&'static str);
+
+#[test]
+fn pretty() {
+	assert_eq!(format!("{}", Padding("a")), "[a]");
+	assert_eq!(format!("{:#}", Padding("a")), "[ a ]");
+}
+
+#[test]
+fn padding() {
+	assert_eq!(format!("{:>6}", Padding("a")), "   [a]");
+	assert_eq!(format!("{:*^9}", Padding("a")), "***[a]***");
+	assert_eq!(format!("{:-<#8}", Padding("a")), "[ a ]---");
+	assert_eq!(format!("{:2}", Padding("a")), "[a]");
+}
+
+#[test]
+fn padding_error() {
+	struct Broken;
+	impl fmt::Display for Broken {
+		fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result { Err(fmt::Error) }
+	}
+
+	// The error is returned instead of panicking
+	let broken = Broken;
+	let text = t4!("[<#= broken #>]");
+	assert!(write!(String::new(), "{:>10}", text).is_err());
+	assert!(write!(String::new(), "{:<#10}", text).is_err());
+}
+
+#[test]
+fn inline_padding() {
+	let name = "Splamy";
+	assert_eq!(format!("{:>10}", t4!("<#= name #>!")), "   Splamy!");
+}
+
+#[test]
+fn inline_pretty() {
+	// The variable of the caller is not shadowed
+	let pretty = "yes";
+	assert_eq!(format!("{:#}", t4!("<#= pretty #>")), "yes");
+}
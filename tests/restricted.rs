@@ -0,0 +1,26 @@
+use t4rust_derive::Template;
+
+#[derive(Template)]
+#[template(path = "./tests/restricted.tt", allow = "if, for")]
+struct Restricted {
+	items: Vec<&'static str>,
+}
+
+#[derive(Template)]
+#[template(path = "./tests/simple_template.tt", restricted)]
+struct ExpressionsOnly {
+	text: &'static str,
+}
+
+#[test]
+fn restricted_control_flow() {
+	let f = format!("{}", Restricted { items: vec!["long", "", "abc"] });
+	assert_eq!(f, "LONG\n(empty)\nabc\n");
+}
+
+#[test]
+fn restricted_expressions_only() {
+	let f = format!("{}", ExpressionsOnly { text: "Inner" });
+	let f = f.trim_end_matches(['\r', '\n']);
+	assert_eq!(f, "Text Inner Other Text");
+}
@@ -0,0 +1,39 @@
+use t4rust_derive::Template;
+
+#[derive(Template)]
+#[template(
+	path = "./tests/source_markers.html.tt",
+	cleanws,
+	markers = "<!-- t4:{file}:{line} -->"
+)]
+struct Page {
+	title: &'static str,
+	items: Vec<&'static str>,
+}
+
+#[derive(Template)]
+#[template(
+	path = "./tests/source_markers.html.tt",
+	markers = "[{line}:{output}]"
+)]
+struct Wrapped {
+	title: &'static str,
+	items: Vec<&'static str>,
+}
+
+#[test]
+fn source_markers() {
+	let page = Page { title: "<Fruit>", items: vec!["Apple"] };
+	assert_eq!(
+		page.to_string(),
+		"<!-- t4:tests/source_markers.html.tt:1 --><h1><!-- \
+		 t4:tests/source_markers.html.tt:1 -->&lt;Fruit&gt;<!-- \
+		 t4:tests/source_markers.html.tt:1 --></h1>\n<!-- \
+		 t4:tests/source_markers.html.tt:3 --><li><!-- \
+		 t4:tests/source_markers.html.tt:3 -->Apple<!-- \
+		 t4:tests/source_markers.html.tt:3 --></li>\n"
+	);
+
+	let wrapped = Wrapped { title: "T", items: vec![] };
+	assert_eq!(wrapped.to_string(), "[1:<h1>][1:T][1:</h1>\n][4:\n]");
+}
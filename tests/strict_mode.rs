@@ -0,0 +1,15 @@
+use t4rust_derive::Template;
+
+#[derive(Template)]
+#[template(path = "./tests/strict_mode.html.tt", strict)]
+struct StrictMode {
+	items: Vec<&'static str>,
+	footer: &'static str,
+}
+
+#[test]
+fn strict_mode() {
+	let f =
+		format!("{}", StrictMode { items: vec!["<a>", "&"], footer: "<hr>" });
+	assert_eq!(f, "<li>&lt;a&gt;</li>\n<li>&amp;</li>\n<hr>\n");
+}
@@ -0,0 +1,13 @@
+use t4rust_derive::Template;
+
+#[derive(Template)]
+#[template(path = "./tests/template_attribute.tt", escape = "html", cleanws)]
+struct TemplateAttribute {
+	text: String,
+}
+
+#[test]
+fn template_attribute() {
+	let f = format!("{}", TemplateAttribute { text: "<b> & \"x\"".into() });
+	assert_eq!(f, "<p>&lt;b&gt; &amp; &quot;x&quot;</p>\n<b> & \"x\"\n");
+}
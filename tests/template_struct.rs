@@ -0,0 +1,46 @@
+use t4rust_derive::template_struct;
+
+template_struct!("./tests/template_struct.tt");
+template_struct!(Greeting, "./tests/template_struct_defaults.tt");
+
+#[test]
+fn template_struct() {
+	let report = TemplateStruct {
+		title: "Report".into(),
+		rows: vec![("a".into(), 1), ("b".into(), 2)],
+		footer: "",
+	};
+	assert_eq!(
+		report.to_string().trim_end_matches(['\r', '\n']),
+		"# Report\na: 1\nb: 2"
+	);
+}
+
+#[test]
+fn template_struct_builder() {
+	let report = TemplateStruct::builder()
+		.title("Builder")
+		.rows(vec![("c".into(), 3)])
+		.build()
+		.unwrap();
+	assert_eq!(report.footer, "-- end --");
+	assert_eq!(
+		report.to_string().trim_end_matches(['\r', '\n']),
+		"# Builder\nc: 3\n-- end --"
+	);
+
+	assert_eq!(TemplateStruct::builder().title("x").build().err(), Some("rows"));
+}
+
+#[test]
+fn template_struct_defaults() {
+	assert_eq!(
+		Greeting::default().to_string().trim_end_matches(['\r', '\n']),
+		"Hello World!"
+	);
+	let greeting = Greeting::builder().name("Splamy").build().unwrap();
+	assert_eq!(
+		greeting.to_string().trim_end_matches(['\r', '\n']),
+		"Hello Splamy!"
+	);
+}